@@ -0,0 +1,483 @@
+//! Holding back the keys that come in while a tap-hold, tap-dance or one-shot
+//! key is undecided, and replaying them once it is.
+//!
+//! rmk would send every other key right away, so pressing such a key toggles
+//! the hold-back layer on in rmk, where no key of the matrix has an action.
+//! The actions of the key are on hidden keys, positions that are not wired up,
+//! and a hidden key, the gate, toggles the layer. `HoldBack` works on plain
+//! `(row, col)` events and millisecond timestamps and tells which events to
+//! send to rmk, `tap_hold_keys.rs` does that on the firmware:
+//!
+//! - Events of the hold-back layer are queued while the key is undecided, up to
+//!   `MAX_HELD_BACK`. The one past that decides the key right away, as a hold,
+//!   and is replayed after the queue.
+//! - Once the key is decided, come its hidden keys, then a tap of the gate that
+//!   turns the layer off, then the queued events in the order they came in.
+//! - Each tap of the gate is counted until rmk reports its press. Events of the
+//!   layer that come with nothing pending were on their way before the gate,
+//!   they are replayed. A press while no tap of the gate is on its way means
+//!   that the layer is on by mistake, and the gate is tapped again. Releases
+//!   are replayed either way, rmk takes them from the layer that the key was
+//!   pressed on.
+//! - A decided key holds its hidden key down until it is released, for up to
+//!   `MAX_DOWN` keys at once. Past that the hidden key is tapped instead, so
+//!   that no release is ever missing.
+
+use crate::one_shot::{self, OneShot};
+use crate::tap_dance::{self, Dance};
+use crate::tap_hold::{self, Pending, Profile};
+
+/// Key events that can wait for a decision, the key is decided beyond that
+pub const MAX_HELD_BACK: usize = 16;
+
+/// Decided keys that can be down at the same time
+pub const MAX_DOWN: usize = 4;
+
+/// Events of a single step, at most the hidden keys of a decision, the
+/// releases of the keys that are down, the gate and the held back events
+const MAX_EVENTS: usize = 2 * 2 + MAX_DOWN + 2 + MAX_HELD_BACK + 1 + MAX_DOWN;
+
+/// A key at `(row, col)` that was pressed or released
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub pos: (u8, u8),
+    pub pressed: bool,
+}
+
+impl KeyEvent {
+    pub fn press(pos: (u8, u8)) -> Self {
+        Self { pos, pressed: true }
+    }
+
+    pub fn release(pos: (u8, u8)) -> Self {
+        Self {
+            pos,
+            pressed: false,
+        }
+    }
+}
+
+/// Key events to send to rmk, in order
+#[derive(Clone, Debug)]
+pub struct Events {
+    events: [KeyEvent; MAX_EVENTS],
+    len: usize,
+}
+
+impl Events {
+    fn new() -> Self {
+        Self {
+            events: [KeyEvent::default(); MAX_EVENTS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: KeyEvent) {
+        self.events[self.len] = event;
+        self.len += 1;
+    }
+
+    fn tap(&mut self, pos: (u8, u8)) {
+        self.push(KeyEvent::press(pos));
+        self.push(KeyEvent::release(pos));
+    }
+
+    pub fn as_slice(&self) -> &[KeyEvent] {
+        &self.events[..self.len]
+    }
+}
+
+/// A tap-hold key and the hidden keys that carry its actions, as `(row, col)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapHoldKey {
+    pub profile: Profile,
+    pub tap: (u8, u8),
+    pub hold: (u8, u8),
+}
+
+/// A tap-dance key and the hidden keys that carry its actions, as `(row, col)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TapDanceKey {
+    /// How long to wait for the next press or release
+    pub term_ms: u64,
+    pub tap: (u8, u8),
+    /// Tapping `tap` twice if there is none
+    pub double_tap: Option<(u8, u8)>,
+    pub hold: (u8, u8),
+    /// Tapping and then holding `tap` if there is none
+    pub tap_hold: Option<(u8, u8)>,
+}
+
+/// A one-shot modifier or layer key and the hidden key that carries it, as `(row, col)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OneShotKey {
+    /// How long a tap waits for the next key
+    pub timeout_ms: u64,
+    pub hidden: (u8, u8),
+}
+
+#[derive(Clone, Debug)]
+enum Deciding {
+    TapHold(TapHoldKey, Pending),
+    TapDance(TapDanceKey, Dance),
+    OneShot(OneShotKey, OneShot),
+}
+
+#[derive(Clone, Debug)]
+struct PendingKey {
+    pos: (u8, u8),
+    deciding: Deciding,
+}
+
+/// What a decided key does with a hidden key
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    Tap((u8, u8)),
+    /// Press it until the key is released
+    Hold((u8, u8)),
+    /// Press it until the key at the second position is released
+    HoldWhile((u8, u8), (u8, u8)),
+    /// Release what the key holds down
+    Cancel,
+}
+
+type Steps = [Option<Step>; 2];
+
+/// A decided key that is down and the hidden key it holds down
+type Down = ((u8, u8), (u8, u8));
+
+fn tap_hold_steps(key: &TapHoldKey, decision: tap_hold::Decision) -> Steps {
+    let step = match decision {
+        tap_hold::Decision::Tap => Step::Tap(key.tap),
+        tap_hold::Decision::Hold => Step::Hold(key.hold),
+    };
+    [Some(step), None]
+}
+
+fn one_shot_steps(key: &OneShotKey, decision: one_shot::Decision, next: (u8, u8)) -> Steps {
+    let step = match decision {
+        one_shot::Decision::NextKey => Step::HoldWhile(key.hidden, next),
+        one_shot::Decision::Hold => Step::Hold(key.hidden),
+        one_shot::Decision::Cancel => Step::Cancel,
+    };
+    [Some(step), None]
+}
+
+fn tap_dance_steps(key: &TapDanceKey, decision: tap_dance::Decision) -> Steps {
+    match decision {
+        tap_dance::Decision::Tap => [Some(Step::Tap(key.tap)), None],
+        tap_dance::Decision::DoubleTap => match key.double_tap {
+            Some(double_tap) => [Some(Step::Tap(double_tap)), None],
+            None => [Some(Step::Tap(key.tap)), Some(Step::Tap(key.tap))],
+        },
+        tap_dance::Decision::Hold => [Some(Step::Hold(key.hold)), None],
+        tap_dance::Decision::TapHold => match key.tap_hold {
+            Some(tap_hold) => [Some(Step::Hold(tap_hold)), None],
+            None => [Some(Step::Tap(key.tap)), Some(Step::Hold(key.tap))],
+        },
+    }
+}
+
+/// Steps for a pending key that can't wait any longer, `next` is the key
+/// that came in last
+fn decide_now(pending: &PendingKey, next: (u8, u8)) -> Steps {
+    match &pending.deciding {
+        Deciding::TapHold(key, _) => tap_hold_steps(key, tap_hold::Decision::Hold),
+        Deciding::TapDance(key, dance) => tap_dance_steps(key, dance.decision()),
+        Deciding::OneShot(key, one_shot) => {
+            let decision = one_shot.other_key(true).unwrap_or(one_shot::Decision::Hold);
+            one_shot_steps(key, decision, next)
+        }
+    }
+}
+
+/// The keys that hold back others, and what they hold back
+#[derive(Clone, Debug)]
+pub struct HoldBack {
+    /// The hidden key that toggles the hold-back layer
+    gate: (u8, u8),
+    pending: Option<PendingKey>,
+    /// Key events that came in while `pending` wasn't decided
+    held_back: [KeyEvent; MAX_HELD_BACK],
+    held_back_len: usize,
+    /// Decided keys that are still down, with the hidden key they hold down
+    down: [Option<Down>; MAX_DOWN],
+    /// Taps of the gate that rmk hasn't reported yet
+    gate_taps: u8,
+    /// Position and time of the last tap of a tap-hold key, for the quick tap term
+    last_tap: Option<((u8, u8), u64)>,
+}
+
+impl HoldBack {
+    pub fn new(gate: (u8, u8)) -> Self {
+        Self {
+            gate,
+            pending: None,
+            held_back: [KeyEvent::default(); MAX_HELD_BACK],
+            held_back_len: 0,
+            down: [None; MAX_DOWN],
+            gate_taps: 0,
+            last_tap: None,
+        }
+    }
+
+    /// When the pending key is decided by waiting
+    pub fn deadline(&self) -> Option<u64> {
+        match &self.pending.as_ref()?.deciding {
+            Deciding::TapHold(_, pending) => Some(pending.deadline()),
+            Deciding::TapDance(_, dance) => Some(dance.deadline()),
+            Deciding::OneShot(_, one_shot) => one_shot.deadline(),
+        }
+    }
+
+    /// Whether a one-shot key is pending, which the joystick decides
+    pub fn wants_pointer(&self) -> bool {
+        matches!(
+            self.pending,
+            Some(PendingKey {
+                deciding: Deciding::OneShot(..),
+                ..
+            })
+        )
+    }
+
+    /// Toggle the hold-back layer
+    pub fn tap_gate(&mut self) -> Events {
+        let mut out = Events::new();
+        self.gate(&mut out);
+        out
+    }
+
+    /// The tap-hold `key` at `pos` was pressed
+    pub fn press_tap_hold(&mut self, pos: (u8, u8), key: TapHoldKey, now: u64) -> Events {
+        let mut out = Events::new();
+        let last_tap = self.last_tap.filter(|(p, _)| *p == pos).map(|(_, t)| t);
+        if tap_hold::is_quick_tap(&key.profile, last_tap, now) {
+            // It toggled the hold-back layer on, there is nothing to decide
+            self.gate(&mut out);
+            self.hold(pos, key.tap, &mut out);
+            return out;
+        }
+        self.start(pos, Deciding::TapHold(key, Pending::new(key.profile, now)));
+        out
+    }
+
+    /// The tap-dance `key` at `pos` was pressed for the first time
+    pub fn press_tap_dance(&mut self, pos: (u8, u8), key: TapDanceKey, now: u64) {
+        self.start(pos, Deciding::TapDance(key, Dance::new(key.term_ms, now)));
+    }
+
+    /// The one-shot `key` at `pos` was pressed
+    pub fn press_one_shot(&mut self, pos: (u8, u8), key: OneShotKey) {
+        self.start(pos, Deciding::OneShot(key, OneShot::new(key.timeout_ms)));
+    }
+
+    /// The key turned on the hold-back layer as it was pressed
+    fn start(&mut self, pos: (u8, u8), deciding: Deciding) {
+        if self.pending.is_some() {
+            // Only if the hold-back layer was off by mistake, it's on again now
+            return;
+        }
+        self.pending = Some(PendingKey { pos, deciding });
+    }
+
+    /// Look at a key event before anything else, with what to send if it's
+    /// taken care of.
+    ///
+    /// `held_back` is set for events of the hold-back layer, which is also on
+    /// while `leader` is.
+    pub fn key_event(
+        &mut self,
+        event: KeyEvent,
+        held_back: bool,
+        leader: bool,
+        now: u64,
+    ) -> Option<Events> {
+        let pos = event.pos;
+        let mut out = Events::new();
+        if pos == self.gate {
+            if event.pressed {
+                self.gate_taps = self.gate_taps.saturating_sub(1);
+            }
+            return Some(out);
+        }
+        if held_back && self.pending.is_none() && !leader {
+            self.replay_late(event, &mut out);
+            return Some(out);
+        }
+        let mut taken = false;
+        // Held back or still deciding, what it holds down stays down for now
+        let mut keep_down = false;
+        if let Some(pending) = self.pending.as_mut() {
+            let mut overflow = None;
+            let steps = if pos == pending.pos {
+                taken = true;
+                match &mut pending.deciding {
+                    Deciding::TapHold(key, pending) if !event.pressed => {
+                        Some(tap_hold_steps(key, pending.release()))
+                    }
+                    Deciding::TapHold(..) => None,
+                    Deciding::TapDance(_, dance) if event.pressed => {
+                        dance.press(now);
+                        None
+                    }
+                    Deciding::TapDance(key, dance) => dance
+                        .release(now)
+                        .map(|decision| tap_dance_steps(key, decision)),
+                    Deciding::OneShot(key, one_shot) if event.pressed => one_shot
+                        .press()
+                        .map(|decision| one_shot_steps(key, decision, pos)),
+                    Deciding::OneShot(_, one_shot) => {
+                        one_shot.release(now);
+                        None
+                    }
+                }
+            } else if !held_back {
+                None
+            } else if self.held_back_len == MAX_HELD_BACK {
+                taken = true;
+                keep_down = true;
+                overflow = Some(event);
+                Some(decide_now(pending, pos))
+            } else {
+                taken = true;
+                keep_down = true;
+                self.held_back[self.held_back_len] = event;
+                self.held_back_len += 1;
+                match &mut pending.deciding {
+                    Deciding::TapHold(key, pending) => pending
+                        .other_key(pos, event.pressed)
+                        .map(|decision| tap_hold_steps(key, decision)),
+                    Deciding::TapDance(key, dance) => dance
+                        .other_key(pos, event.pressed)
+                        .map(|decision| tap_dance_steps(key, decision)),
+                    Deciding::OneShot(key, one_shot) => one_shot
+                        .other_key(event.pressed)
+                        .map(|decision| one_shot_steps(key, decision, pos)),
+                }
+            };
+            if let Some(steps) = steps {
+                self.decide(&steps, now, &mut out);
+            }
+            // After the events that were held back
+            if let Some(event) = overflow {
+                out.push(event);
+            }
+        }
+        if event.pressed || keep_down || self.pending.as_ref().is_some_and(|p| p.pos == pos) {
+            return taken.then_some(out);
+        }
+        let released = self.release_down(pos, &mut out);
+        (released || taken).then_some(out)
+    }
+
+    /// Decide the pending key if it has waited for long enough
+    pub fn timeout(&mut self, now: u64) -> Events {
+        let mut out = Events::new();
+        let Some(pending) = self.pending.as_ref() else {
+            return out;
+        };
+        let steps = match &pending.deciding {
+            Deciding::TapHold(key, pending) => pending
+                .timeout(now)
+                .map(|decision| tap_hold_steps(key, decision)),
+            Deciding::TapDance(key, dance) => dance
+                .timeout(now)
+                .map(|decision| tap_dance_steps(key, decision)),
+            Deciding::OneShot(key, one_shot) => one_shot
+                .timeout(now)
+                .map(|decision| one_shot_steps(key, decision, pending.pos)),
+        };
+        if let Some(steps) = steps {
+            self.decide(&steps, now, &mut out);
+        }
+        out
+    }
+
+    /// A joystick moved the pointer or scrolled
+    pub fn pointer(&mut self, now: u64) -> Events {
+        let mut out = Events::new();
+        let Some(PendingKey {
+            pos,
+            deciding: Deciding::OneShot(key, one_shot),
+        }) = self.pending.as_ref()
+        else {
+            return out;
+        };
+        let steps = one_shot_steps(key, one_shot.pointer(), *pos);
+        self.decide(&steps, now, &mut out);
+        out
+    }
+
+    fn gate(&mut self, out: &mut Events) {
+        self.gate_taps = self.gate_taps.saturating_add(1);
+        out.tap(self.gate);
+    }
+
+    /// Replay an event of the hold-back layer that came in with nothing pending
+    fn replay_late(&mut self, event: KeyEvent, out: &mut Events) {
+        // Unless it was on its way before the gate, the layer is on by mistake.
+        // Releases come from rmk's layer cache and tell nothing.
+        if event.pressed && self.gate_taps == 0 {
+            self.gate(out);
+        }
+        out.push(event);
+    }
+
+    /// Press the hidden key until the key at `pos` is released, or tap it if
+    /// there is no room to remember it
+    fn hold(&mut self, pos: (u8, u8), hidden: (u8, u8), out: &mut Events) {
+        let Some(slot) = self.down.iter_mut().find(|d| d.is_none()) else {
+            out.tap(hidden);
+            return;
+        };
+        *slot = Some((pos, hidden));
+        out.push(KeyEvent::press(hidden));
+    }
+
+    /// Release the hidden keys that the key at `pos` holds down
+    fn release_down(&mut self, pos: (u8, u8), out: &mut Events) -> bool {
+        let mut released = false;
+        for slot in self.down.iter_mut() {
+            if let Some((_, hidden)) = slot.filter(|(p, _)| *p == pos) {
+                *slot = None;
+                out.push(KeyEvent::release(hidden));
+                released = true;
+            }
+        }
+        released
+    }
+
+    /// Send what the pending key was decided to do, then everything that was held back
+    fn decide(&mut self, steps: &Steps, now: u64, out: &mut Events) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        for step in steps.iter().flatten() {
+            match *step {
+                Step::Tap(hidden) => out.tap(hidden),
+                Step::Hold(hidden) => self.hold(pending.pos, hidden, out),
+                Step::HoldWhile(hidden, pos) => {
+                    self.hold(pos, hidden, out);
+                    // One-shot keys in a row apply to the same key
+                    for (p, _) in self.down.iter_mut().flatten() {
+                        if *p == pending.pos {
+                            *p = pos;
+                        }
+                    }
+                }
+                Step::Cancel => {
+                    self.release_down(pending.pos, out);
+                }
+            }
+        }
+        if let (Deciding::TapHold(..), [Some(Step::Tap(_)), None]) = (&pending.deciding, steps) {
+            self.last_tap = Some((pending.pos, now));
+        }
+        self.gate(out);
+        for event in &self.held_back[..self.held_back_len] {
+            out.push(*event);
+        }
+        self.held_back_len = 0;
+    }
+}
@@ -0,0 +1,225 @@
+//! Which keys the host's keyboard layout needs to produce a character.
+//!
+//! The keymap sends key positions, and the host decides what they mean. To type
+//! a character, the firmware needs to know the layout that the host is set to.
+//! The tables in here are plain data and don't depend on rmk.
+
+use crate::usage::{self, *};
+
+/// Keyboard layout that the host is set to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum HostLayout {
+    Us = 0,
+    /// German QWERTZ, as on Windows. `^` and `` ` `` are dead keys.
+    German = 1,
+}
+
+impl HostLayout {
+    pub const ALL: [HostLayout; 2] = [HostLayout::Us, HostLayout::German];
+
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Self::ALL.get(v as usize).copied()
+    }
+
+    /// The layout after this one, for a key that cycles through them
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn table(self) -> &'static Layout {
+        match self {
+            HostLayout::Us => &US,
+            HostLayout::German => &GERMAN,
+        }
+    }
+
+    /// How to type `c`, `None` if the layout doesn't have it
+    pub fn keystroke(self, c: char) -> Option<Keystroke> {
+        let table = self.table();
+        if c.is_ascii_alphabetic() {
+            let lower = c.to_ascii_lowercase();
+            let key = table
+                .moved_letters
+                .iter()
+                .find(|(l, _)| *l == lower)
+                .map(|(_, key)| *key)
+                .unwrap_or(usage::letter(lower as u8));
+            let modifiers = if c.is_ascii_uppercase() {
+                MOD_LSHIFT
+            } else {
+                0
+            };
+            return Some(Keystroke {
+                modifiers,
+                key,
+                dead: false,
+            });
+        }
+        table
+            .characters
+            .iter()
+            .find(|(ch, _)| *ch == c)
+            .map(|(_, k)| *k)
+    }
+}
+
+/// A key with the modifiers to hold while pressing it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keystroke {
+    pub modifiers: u8,
+    pub key: u8,
+    /// The key is a dead key, the character only appears after a `Space`
+    pub dead: bool,
+}
+
+/// Everything a layout can type
+pub struct Layout {
+    /// Every character but letters
+    pub characters: &'static [(char, Keystroke)],
+    /// Lowercase letters that are somewhere else than on a US layout
+    pub moved_letters: &'static [(char, u8)],
+}
+
+const fn key(key: u8) -> Keystroke {
+    Keystroke {
+        modifiers: 0,
+        key,
+        dead: false,
+    }
+}
+
+const fn shift(key: u8) -> Keystroke {
+    Keystroke {
+        modifiers: MOD_LSHIFT,
+        key,
+        dead: false,
+    }
+}
+
+const fn altgr(key: u8) -> Keystroke {
+    Keystroke {
+        modifiers: MOD_RALT,
+        key,
+        dead: false,
+    }
+}
+
+const fn dead(k: Keystroke) -> Keystroke {
+    Keystroke { dead: true, ..k }
+}
+
+pub const US: Layout = Layout {
+    characters: &[
+        ('\n', key(ENTER)),
+        ('\t', key(TAB)),
+        (' ', key(SPACE)),
+        ('0', key(digit(0))),
+        ('1', key(digit(1))),
+        ('2', key(digit(2))),
+        ('3', key(digit(3))),
+        ('4', key(digit(4))),
+        ('5', key(digit(5))),
+        ('6', key(digit(6))),
+        ('7', key(digit(7))),
+        ('8', key(digit(8))),
+        ('9', key(digit(9))),
+        ('!', shift(digit(1))),
+        ('@', shift(digit(2))),
+        ('#', shift(digit(3))),
+        ('$', shift(digit(4))),
+        ('%', shift(digit(5))),
+        ('^', shift(digit(6))),
+        ('&', shift(digit(7))),
+        ('*', shift(digit(8))),
+        ('(', shift(digit(9))),
+        (')', shift(digit(0))),
+        ('-', key(MINUS)),
+        ('_', shift(MINUS)),
+        ('=', key(EQUAL)),
+        ('+', shift(EQUAL)),
+        ('[', key(LEFT_BRACKET)),
+        ('{', shift(LEFT_BRACKET)),
+        (']', key(RIGHT_BRACKET)),
+        ('}', shift(RIGHT_BRACKET)),
+        ('\\', key(BACKSLASH)),
+        ('|', shift(BACKSLASH)),
+        (';', key(SEMICOLON)),
+        (':', shift(SEMICOLON)),
+        ('\'', key(QUOTE)),
+        ('"', shift(QUOTE)),
+        ('`', key(GRAVE)),
+        ('~', shift(GRAVE)),
+        (',', key(COMMA)),
+        ('<', shift(COMMA)),
+        ('.', key(DOT)),
+        ('>', shift(DOT)),
+        ('/', key(SLASH)),
+        ('?', shift(SLASH)),
+    ],
+    moved_letters: &[],
+};
+
+pub const GERMAN: Layout = Layout {
+    characters: &[
+        ('\n', key(ENTER)),
+        ('\t', key(TAB)),
+        (' ', key(SPACE)),
+        ('0', key(digit(0))),
+        ('1', key(digit(1))),
+        ('2', key(digit(2))),
+        ('3', key(digit(3))),
+        ('4', key(digit(4))),
+        ('5', key(digit(5))),
+        ('6', key(digit(6))),
+        ('7', key(digit(7))),
+        ('8', key(digit(8))),
+        ('9', key(digit(9))),
+        ('!', shift(digit(1))),
+        ('"', shift(digit(2))),
+        ('§', shift(digit(3))),
+        ('$', shift(digit(4))),
+        ('%', shift(digit(5))),
+        ('&', shift(digit(6))),
+        ('/', shift(digit(7))),
+        ('(', shift(digit(8))),
+        (')', shift(digit(9))),
+        ('=', shift(digit(0))),
+        ('²', altgr(digit(2))),
+        ('³', altgr(digit(3))),
+        ('{', altgr(digit(7))),
+        ('[', altgr(digit(8))),
+        (']', altgr(digit(9))),
+        ('}', altgr(digit(0))),
+        ('ß', key(MINUS)),
+        ('?', shift(MINUS)),
+        ('\\', altgr(MINUS)),
+        ('`', dead(shift(EQUAL))),
+        ('@', altgr(Q)),
+        ('€', altgr(E)),
+        ('ü', key(LEFT_BRACKET)),
+        ('Ü', shift(LEFT_BRACKET)),
+        ('+', key(RIGHT_BRACKET)),
+        ('*', shift(RIGHT_BRACKET)),
+        ('~', altgr(RIGHT_BRACKET)),
+        ('ö', key(SEMICOLON)),
+        ('Ö', shift(SEMICOLON)),
+        ('ä', key(QUOTE)),
+        ('Ä', shift(QUOTE)),
+        ('#', key(NON_US_HASH)),
+        ('\'', shift(NON_US_HASH)),
+        ('^', dead(key(GRAVE))),
+        ('°', shift(GRAVE)),
+        ('<', key(NON_US_BACKSLASH)),
+        ('>', shift(NON_US_BACKSLASH)),
+        ('|', altgr(NON_US_BACKSLASH)),
+        ('µ', altgr(M)),
+        (',', key(COMMA)),
+        (';', shift(COMMA)),
+        ('.', key(DOT)),
+        (':', shift(DOT)),
+        ('-', key(SLASH)),
+        ('_', shift(SLASH)),
+    ],
+    moved_letters: &[('y', Z), ('z', Y)],
+};
@@ -0,0 +1,27 @@
+//! Conditional layers, which are on while some other layers are.
+//!
+//! The build script writes the `[[conditional_layer]]` rules into the firmware, and
+//! `layers.rs` holds on the layers that `resolve()` asks for. Layers are bit
+//! masks here, bit `n` is layer `n`.
+
+/// Layer `then` is on while all layers in `when` are
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub when: u32,
+    pub then: u8,
+}
+
+/// The layers that `rules` turn on when `active` are on otherwise.
+///
+/// Rules apply in order, so a layer that one turns on can turn on another in a
+/// later rule. Layers that are already on aren't turned on again.
+pub fn resolve(rules: &[Rule], active: u32) -> u32 {
+    let on = rules.iter().fold(active, |on, rule| {
+        if on & rule.when == rule.when {
+            on | 1 << rule.then
+        } else {
+            on
+        }
+    });
+    on & !active
+}
@@ -0,0 +1,133 @@
+//! Leader key sequences, matched with a trie.
+//!
+//! The build script builds the trie from the `[[leader.sequence]]` blocks with
+//! `build()` and writes its nodes into the firmware, where `Leader` walks it
+//! one key at a time. Keys are plain numbers here, the firmware uses positions.
+
+/// No key, child, sibling or action
+pub const NONE: u16 = u16::MAX;
+
+/// A node of the trie, `nodes[0]` is the root
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Node {
+    /// The key that leads here from the parent
+    pub key: u16,
+    pub first_child: u16,
+    pub next_sibling: u16,
+    /// Index of the sequence that ends here
+    pub action: u16,
+}
+
+impl Node {
+    pub const EMPTY: Node = Node {
+        key: NONE,
+        first_child: NONE,
+        next_sibling: NONE,
+        action: NONE,
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// Sequence `.0` has no keys
+    Empty(usize),
+    /// Sequence `.0` has the same keys as an earlier one
+    Duplicate(usize),
+    /// The sequences need more nodes than there are
+    TooManyNodes,
+}
+
+/// Build the trie of `sequences` in `nodes`, the action of each sequence is its
+/// index. Returns the number of nodes used.
+pub fn build(sequences: &[&[u16]], nodes: &mut [Node]) -> Result<usize, BuildError> {
+    if nodes.is_empty() {
+        return Err(BuildError::TooManyNodes);
+    }
+    nodes[0] = Node::EMPTY;
+    let mut used = 1;
+    for (i, keys) in sequences.iter().enumerate() {
+        if keys.is_empty() {
+            return Err(BuildError::Empty(i));
+        }
+        let mut node = 0;
+        for &key in keys.iter() {
+            node = match child(nodes, node, key) {
+                Some(child) => child,
+                None => {
+                    if used == nodes.len() {
+                        return Err(BuildError::TooManyNodes);
+                    }
+                    nodes[used] = Node {
+                        key,
+                        next_sibling: nodes[node].first_child,
+                        ..Node::EMPTY
+                    };
+                    nodes[node].first_child = used as u16;
+                    used += 1;
+                    used - 1
+                }
+            };
+        }
+        if nodes[node].action != NONE {
+            return Err(BuildError::Duplicate(i));
+        }
+        nodes[node].action = i as u16;
+    }
+    Ok(used)
+}
+
+/// The child of `node` that `key` leads to
+fn child(nodes: &[Node], node: usize, key: u16) -> Option<usize> {
+    let mut child = nodes[node].first_child;
+    while child != NONE {
+        if nodes[child as usize].key == key {
+            return Some(child as usize);
+        }
+        child = nodes[child as usize].next_sibling;
+    }
+    None
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// More keys can follow
+    Pending,
+    /// The sequence with this action was typed
+    Done(u16),
+    /// No sequence starts with the keys typed
+    Failed,
+}
+
+/// The keys typed after the Leader key so far
+#[derive(Clone, Debug)]
+pub struct Leader<'a> {
+    nodes: &'a [Node],
+    node: usize,
+}
+
+impl<'a> Leader<'a> {
+    pub fn new(nodes: &'a [Node]) -> Self {
+        Self { nodes, node: 0 }
+    }
+
+    /// Another key was typed
+    pub fn key(&mut self, key: u16) -> Step {
+        let Some(node) = child(self.nodes, self.node, key) else {
+            return Step::Failed;
+        };
+        self.node = node;
+        let node = &self.nodes[node];
+        match (node.action, node.first_child) {
+            // Longer sequences start with this one, they get until the timeout
+            (_, child) if child != NONE => Step::Pending,
+            (NONE, _) => Step::Failed,
+            (action, _) => Step::Done(action),
+        }
+    }
+
+    /// The action of the keys typed so far, once no more keys are coming
+    pub fn timeout(&self) -> Option<u16> {
+        let action = self.nodes[self.node].action;
+        (action != NONE).then_some(action)
+    }
+}
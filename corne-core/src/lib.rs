@@ -0,0 +1,20 @@
+//! The parts of the corne-rmk firmware that don't depend on rmk or the hardware.
+//!
+//! The firmware, its build script and corne-tool all use them from here, and
+//! the tests run on the host.
+
+#![no_std]
+
+pub mod hold_back;
+pub mod host_layout;
+pub mod layer_rules;
+pub mod leader;
+pub mod one_shot;
+pub mod overrides;
+pub mod protocol;
+pub mod repeat;
+pub mod tap_dance;
+pub mod tap_hold;
+pub mod tuning;
+pub mod unicode;
+pub mod usage;
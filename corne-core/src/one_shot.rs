@@ -0,0 +1,82 @@
+//! Deciding what a one-shot modifier or layer key applies to.
+//!
+//! Tapped, the key applies to the next key that is pressed within its timeout.
+//! Held down while another key is pressed, it works as a plain modifier or
+//! layer key. Decided on plain millisecond timestamps, `tap_hold_keys.rs` does
+//! the rest on the firmware.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Apply to the key that was just pressed, until that one is released
+    NextKey,
+    /// Apply until the one-shot key itself is released
+    Hold,
+    /// Don't apply at all
+    Cancel,
+}
+
+/// A one-shot key that hasn't been used yet
+#[derive(Clone, Debug)]
+pub struct OneShot {
+    timeout_ms: u64,
+    /// When it was tapped, it is still down otherwise
+    released_at: Option<u64>,
+}
+
+impl OneShot {
+    pub fn new(timeout_ms: u64) -> Self {
+        Self {
+            timeout_ms,
+            released_at: None,
+        }
+    }
+
+    /// Whether it was tapped and waits for the next key
+    pub fn is_armed(&self) -> bool {
+        self.released_at.is_some()
+    }
+
+    /// When it stops waiting for the next key, if it was tapped
+    pub fn deadline(&self) -> Option<u64> {
+        self.released_at.map(|t| t + self.timeout_ms)
+    }
+
+    /// The one-shot key was released without another key pressed meanwhile
+    pub fn release(&mut self, now: u64) {
+        self.released_at = Some(now);
+    }
+
+    /// The decision when the one-shot key is pressed once more, which takes it back
+    pub fn press(&self) -> Option<Decision> {
+        self.is_armed().then_some(Decision::Cancel)
+    }
+
+    /// The decision after another key was pressed or released, if any
+    pub fn other_key(&self, pressed: bool) -> Option<Decision> {
+        if !pressed {
+            return None;
+        }
+        Some(if self.is_armed() {
+            Decision::NextKey
+        } else {
+            Decision::Hold
+        })
+    }
+
+    /// The decision once time has passed, if any
+    pub fn timeout(&self, now: u64) -> Option<Decision> {
+        self.deadline()
+            .is_some_and(|deadline| now >= deadline)
+            .then_some(Decision::Cancel)
+    }
+
+    /// The decision when a joystick moves the pointer or scrolls, which is
+    /// only modified while the key is held down
+    pub fn pointer(&self) -> Decision {
+        if self.is_armed() {
+            Decision::Cancel
+        } else {
+            Decision::Hold
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! What the trigger of a key override that `layers` limits to some layers types.
+//!
+//! rmk's forks match the action of a key, not its layer, so the fork of such an
+//! override is on every layer, keeps the modifiers down and its output does
+//! nothing. The firmware sends the report itself instead, see `overrides.rs`
+//! there: on the override's layers its output without the modifiers that
+//! trigger it, elsewhere the trigger with the modifiers that are down.
+
+use crate::repeat::Keystroke;
+
+/// An override limited to some layers, with HID usages and modifier bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LayerOverride {
+    /// Its layers, as bits
+    pub layers: u32,
+    pub trigger: u8,
+    pub output: u8,
+    /// Any of them triggers it
+    pub modifiers: u8,
+    /// They stay down for the output
+    pub keep_modifiers: bool,
+}
+
+impl LayerOverride {
+    /// What the trigger types while `held` modifiers are down, with the layer
+    /// that rmk took it from
+    pub fn keystroke(&self, layer: u8, held: u8) -> Keystroke {
+        if self.layers & 1 << layer == 0 {
+            return Keystroke {
+                key: self.trigger,
+                modifiers: held,
+            };
+        }
+        Keystroke {
+            key: self.output,
+            modifiers: if self.keep_modifiers {
+                held
+            } else {
+                held & !self.modifiers
+            },
+        }
+    }
+}
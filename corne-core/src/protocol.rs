@@ -0,0 +1,379 @@
+//! Raw HID protocol for tuning the joysticks at runtime.
+//!
+//! Packets use VIA's 32 byte raw HID report and its custom value commands
+//! (`id_custom_set_value`, `id_custom_get_value`), on a channel of our own.
+//! `tuning.rs` answers them, the host tool sends them with the same codec.
+//!
+//! ```text
+//! byte 0: command, byte 1: channel, byte 2: value id, byte 3: stick, byte 4..: data
+//! ```
+
+/// Size of a VIA raw HID report
+pub const REPORT_SIZE: usize = 32;
+
+/// VIA `id_custom_set_value`
+pub const CMD_SET_VALUE: u8 = 0x07;
+/// VIA `id_custom_get_value`
+pub const CMD_GET_VALUE: u8 = 0x08;
+/// VIA `id_unhandled`, the first byte of every error response
+pub const CMD_UNHANDLED: u8 = 0xFF;
+
+/// Custom value channel used for the joysticks.
+/// VIA reserves 0-4 for its own lighting and audio channels.
+pub const CHANNEL_JOYSTICK: u8 = 0x80;
+
+/// Number of joysticks, one per half
+pub const NUM_STICKS: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ValueId {
+    Gain = 0x01,
+    Deadzone = 0x02,
+    Curve = 0x03,
+    Role = 0x04,
+    Invert = 0x05,
+    Rotation = 0x06,
+    RawAdc = 0x07,
+    Stream = 0x08,
+    SwapAxes = 0x09,
+}
+
+impl ValueId {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0x01 => Self::Gain,
+            0x02 => Self::Deadzone,
+            0x03 => Self::Curve,
+            0x04 => Self::Role,
+            0x05 => Self::Invert,
+            0x06 => Self::Rotation,
+            0x07 => Self::RawAdc,
+            0x08 => Self::Stream,
+            0x09 => Self::SwapAxes,
+            _ => return None,
+        })
+    }
+}
+
+/// What the stick output is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Role {
+    Off = 0,
+    Pointer = 1,
+    Scroll = 2,
+}
+
+impl Role {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0 => Self::Off,
+            1 => Self::Pointer,
+            2 => Self::Scroll,
+            _ => return None,
+        })
+    }
+
+    /// The role after this one, for a key that cycles through them
+    pub fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Pointer,
+            Self::Pointer => Self::Scroll,
+            Self::Scroll => Self::Off,
+        }
+    }
+}
+
+/// Response curve applied to the deflection after the deadzone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Curve {
+    Linear = 0,
+    Quadratic = 1,
+    Cubic = 2,
+}
+
+impl Curve {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0 => Self::Linear,
+            1 => Self::Quadratic,
+            2 => Self::Cubic,
+            _ => return None,
+        })
+    }
+}
+
+/// Tunable parameters of one joystick
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JoystickParams {
+    /// Output gain in percent
+    pub gain: u16,
+    /// Deflection below which the stick is considered at rest
+    pub deadzone: u16,
+    pub curve: Curve,
+    pub role: Role,
+    /// Flip the x axis, applied after `swap_axes`
+    pub invert_x: bool,
+    /// Flip the y axis, applied after `swap_axes`
+    pub invert_y: bool,
+    /// Exchange x and y, applied after `rotation`
+    pub swap_axes: bool,
+    /// Clockwise rotation in degrees, 0..360
+    pub rotation: u16,
+}
+
+impl Default for JoystickParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl JoystickParams {
+    pub const DEFAULT: Self = Self {
+        gain: 100,
+        deadzone: 0,
+        curve: Curve::Linear,
+        role: Role::Pointer,
+        invert_x: false,
+        invert_y: false,
+        swap_axes: false,
+        rotation: 0,
+    };
+
+    /// Read a single value
+    pub fn get(&self, id: ValueId) -> Option<Value> {
+        Some(match id {
+            ValueId::Gain => Value::Gain(self.gain),
+            ValueId::Deadzone => Value::Deadzone(self.deadzone),
+            ValueId::Curve => Value::Curve(self.curve),
+            ValueId::Role => Value::Role(self.role),
+            ValueId::Invert => Value::Invert {
+                x: self.invert_x,
+                y: self.invert_y,
+            },
+            ValueId::SwapAxes => Value::SwapAxes(self.swap_axes),
+            ValueId::Rotation => Value::Rotation(self.rotation),
+            ValueId::RawAdc | ValueId::Stream => return None,
+        })
+    }
+
+    /// Write a single value, returns false if it's not a parameter
+    pub fn set(&mut self, value: Value) -> bool {
+        match value {
+            Value::Gain(v) => self.gain = v,
+            Value::Deadzone(v) => self.deadzone = v,
+            Value::Curve(v) => self.curve = v,
+            Value::Role(v) => self.role = v,
+            Value::Invert { x, y } => {
+                self.invert_x = x;
+                self.invert_y = y;
+            }
+            Value::SwapAxes(v) => self.swap_axes = v,
+            Value::Rotation(v) => self.rotation = v % 360,
+            Value::RawAdc(_) | Value::Stream(_) => return false,
+        }
+        true
+    }
+}
+
+/// One raw ADC reading of a stick, before any processing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RawSample {
+    pub x: i16,
+    pub y: i16,
+    /// Incremented on every new reading, wraps around
+    pub seq: u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value {
+    Gain(u16),
+    Deadzone(u16),
+    Curve(Curve),
+    Role(Role),
+    Invert {
+        x: bool,
+        y: bool,
+    },
+    SwapAxes(bool),
+    Rotation(u16),
+    /// Latest raw reading, read only
+    RawAdc(RawSample),
+    /// Whether raw readings are pushed to the host as they arrive
+    Stream(bool),
+}
+
+impl Value {
+    pub fn id(&self) -> ValueId {
+        match self {
+            Value::Gain(_) => ValueId::Gain,
+            Value::Deadzone(_) => ValueId::Deadzone,
+            Value::Curve(_) => ValueId::Curve,
+            Value::Role(_) => ValueId::Role,
+            Value::Invert { .. } => ValueId::Invert,
+            Value::SwapAxes(_) => ValueId::SwapAxes,
+            Value::Rotation(_) => ValueId::Rotation,
+            Value::RawAdc(_) => ValueId::RawAdc,
+            Value::Stream(_) => ValueId::Stream,
+        }
+    }
+
+    fn encode(&self, data: &mut [u8]) {
+        match *self {
+            Value::Gain(v) | Value::Deadzone(v) | Value::Rotation(v) => {
+                data[..2].copy_from_slice(&v.to_le_bytes())
+            }
+            Value::Curve(v) => data[0] = v as u8,
+            Value::Role(v) => data[0] = v as u8,
+            Value::Invert { x, y } => data[0] = x as u8 | (y as u8) << 1,
+            Value::RawAdc(s) => {
+                data[0..2].copy_from_slice(&s.x.to_le_bytes());
+                data[2..4].copy_from_slice(&s.y.to_le_bytes());
+                data[4..6].copy_from_slice(&s.seq.to_le_bytes());
+            }
+            Value::SwapAxes(v) | Value::Stream(v) => data[0] = v as u8,
+        }
+    }
+
+    fn decode(id: ValueId, data: &[u8]) -> Result<Self, Error> {
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let i16_at = |i: usize| i16::from_le_bytes([data[i], data[i + 1]]);
+        Ok(match id {
+            ValueId::Gain => Value::Gain(u16_at(0)),
+            ValueId::Deadzone => Value::Deadzone(u16_at(0)),
+            ValueId::Curve => Value::Curve(Curve::from_u8(data[0]).ok_or(Error::InvalidValue)?),
+            ValueId::Role => Value::Role(Role::from_u8(data[0]).ok_or(Error::InvalidValue)?),
+            ValueId::Invert => Value::Invert {
+                x: data[0] & 0b01 != 0,
+                y: data[0] & 0b10 != 0,
+            },
+            ValueId::SwapAxes => Value::SwapAxes(data[0] != 0),
+            ValueId::Rotation => Value::Rotation(u16_at(0)),
+            ValueId::RawAdc => Value::RawAdc(RawSample {
+                x: i16_at(0),
+                y: i16_at(2),
+                seq: u16_at(4),
+            }),
+            ValueId::Stream => Value::Stream(data[0] != 0),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Error {
+    /// Not a joystick packet, or an unknown command
+    UnknownCommand = 0x01,
+    UnknownValue = 0x02,
+    InvalidStick = 0x03,
+    InvalidValue = 0x04,
+    /// The value can't be written
+    ReadOnly = 0x05,
+}
+
+impl Error {
+    pub fn from_u8(v: u8) -> Self {
+        match v {
+            0x02 => Self::UnknownValue,
+            0x03 => Self::InvalidStick,
+            0x04 => Self::InvalidValue,
+            0x05 => Self::ReadOnly,
+            _ => Self::UnknownCommand,
+        }
+    }
+}
+
+/// Host to keyboard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    Get { stick: u8, id: ValueId },
+    Set { stick: u8, value: Value },
+}
+
+impl Request {
+    pub fn encode(&self) -> [u8; REPORT_SIZE] {
+        let mut buf = [0; REPORT_SIZE];
+        buf[1] = CHANNEL_JOYSTICK;
+        match *self {
+            Request::Get { stick, id } => {
+                buf[0] = CMD_GET_VALUE;
+                buf[2] = id as u8;
+                buf[3] = stick;
+            }
+            Request::Set { stick, value } => {
+                buf[0] = CMD_SET_VALUE;
+                buf[2] = value.id() as u8;
+                buf[3] = stick;
+                value.encode(&mut buf[4..]);
+            }
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8; REPORT_SIZE]) -> Result<Self, Error> {
+        if buf[1] != CHANNEL_JOYSTICK {
+            return Err(Error::UnknownCommand);
+        }
+        let id = ValueId::from_u8(buf[2]).ok_or(Error::UnknownValue)?;
+        let stick = buf[3];
+        if stick as usize >= NUM_STICKS {
+            return Err(Error::InvalidStick);
+        }
+        match buf[0] {
+            CMD_GET_VALUE => Ok(Request::Get { stick, id }),
+            CMD_SET_VALUE => Ok(Request::Set {
+                stick,
+                value: Value::decode(id, &buf[4..])?,
+            }),
+            _ => Err(Error::UnknownCommand),
+        }
+    }
+}
+
+/// Keyboard to host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Response {
+    /// Current value, sent as the answer to a get or set, and for every
+    /// raw reading while streaming is enabled
+    Value {
+        stick: u8,
+        value: Value,
+    },
+    Error(Error),
+}
+
+impl Response {
+    pub fn encode(&self) -> [u8; REPORT_SIZE] {
+        let mut buf = [0; REPORT_SIZE];
+        match *self {
+            Response::Value { stick, value } => {
+                buf[0] = CMD_GET_VALUE;
+                buf[1] = CHANNEL_JOYSTICK;
+                buf[2] = value.id() as u8;
+                buf[3] = stick;
+                value.encode(&mut buf[4..]);
+            }
+            Response::Error(e) => {
+                buf[0] = CMD_UNHANDLED;
+                buf[1] = e as u8;
+            }
+        }
+        buf
+    }
+
+    pub fn decode(buf: &[u8; REPORT_SIZE]) -> Result<Self, Error> {
+        match buf[0] {
+            CMD_UNHANDLED => Ok(Response::Error(Error::from_u8(buf[1]))),
+            CMD_GET_VALUE if buf[1] == CHANNEL_JOYSTICK => {
+                let id = ValueId::from_u8(buf[2]).ok_or(Error::UnknownValue)?;
+                Ok(Response::Value {
+                    stick: buf[3],
+                    value: Value::decode(id, &buf[4..])?,
+                })
+            }
+            _ => Err(Error::UnknownCommand),
+        }
+    }
+}
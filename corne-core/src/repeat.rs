@@ -0,0 +1,28 @@
+//! Finding what the Alt-Repeat key sends after another key.
+//!
+//! Keystrokes are HID usages with modifier bits, as in `usage.rs`. The
+//! firmware side is in `repeat_keys.rs`.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Keystroke {
+    pub key: u8,
+    pub modifiers: u8,
+}
+
+/// The counterpart of `last` among `pairs`, which go both ways.
+///
+/// The pair with the most modifiers in common wins, and the modifiers of
+/// `last` that aren't part of it stay, so Ctrl+Left turns into Ctrl+Right.
+pub fn alternate(pairs: &[(Keystroke, Keystroke)], last: Keystroke) -> Option<Keystroke> {
+    pairs
+        .iter()
+        .flat_map(|&(a, b)| [(a, b), (b, a)])
+        .filter(|(from, _)| {
+            from.key == last.key && last.modifiers & from.modifiers == from.modifiers
+        })
+        .max_by_key(|(from, _)| from.modifiers.count_ones())
+        .map(|(from, to)| Keystroke {
+            key: to.key,
+            modifiers: (last.modifiers & !from.modifiers) | to.modifiers,
+        })
+}
@@ -0,0 +1,90 @@
+//! Deciding what a tap-dance key does: a tap, a double tap, a hold, or a tap
+//! followed by a hold.
+//!
+//! Plain logic on millisecond timestamps like `tap_hold.rs`, the firmware side
+//! is in `tap_hold_keys.rs`.
+
+use crate::tap_hold::Interrupts;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Tap,
+    DoubleTap,
+    Hold,
+    TapHold,
+}
+
+/// A tap-dance key that has been pressed and is not decided yet
+#[derive(Clone, Debug)]
+pub struct Dance {
+    /// How long to wait for the next press or release
+    term_ms: u64,
+    /// Presses so far, 1 or 2
+    presses: u8,
+    down: bool,
+    last_event: u64,
+    /// Keys pressed while this one is down, for a tap of another key inside it
+    others: Interrupts,
+}
+
+impl Dance {
+    /// Start with the first press
+    pub fn new(term_ms: u64, now: u64) -> Self {
+        Self {
+            term_ms,
+            presses: 1,
+            down: true,
+            last_event: now,
+            others: Interrupts::default(),
+        }
+    }
+
+    /// When the key is decided by waiting
+    pub fn deadline(&self) -> u64 {
+        self.last_event + self.term_ms
+    }
+
+    /// The key was pressed again
+    pub fn press(&mut self, now: u64) {
+        self.presses = 2;
+        self.down = true;
+        self.last_event = now;
+        self.others = Interrupts::default();
+    }
+
+    /// The decision when the key is released, if any
+    pub fn release(&mut self, now: u64) -> Option<Decision> {
+        self.down = false;
+        self.last_event = now;
+        // There is no triple tap to wait for
+        (self.presses == 2).then_some(Decision::DoubleTap)
+    }
+
+    /// The decision once time has passed, if any
+    pub fn timeout(&self, now: u64) -> Option<Decision> {
+        (now >= self.deadline()).then(|| self.decision())
+    }
+
+    /// The decision after another key was pressed or released, if any
+    pub fn other_key(&mut self, key: (u8, u8), pressed: bool) -> Option<Decision> {
+        if !self.down {
+            // Typing on, no more taps to come
+            return Some(self.decision());
+        }
+        if pressed {
+            self.others.press(key);
+            return None;
+        }
+        self.others.release(key).then(|| self.decision())
+    }
+
+    /// What the presses so far amount to
+    pub fn decision(&self) -> Decision {
+        match (self.presses, self.down) {
+            (1, false) => Decision::Tap,
+            (1, true) => Decision::Hold,
+            (_, false) => Decision::DoubleTap,
+            (_, true) => Decision::TapHold,
+        }
+    }
+}
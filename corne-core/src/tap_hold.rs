@@ -0,0 +1,104 @@
+//! Deciding whether a tap-hold key is tapped or held, with settings of its own.
+//!
+//! rmk has one tap-hold configuration for the whole keymap. Keys with a profile
+//! in `keyboard.toml` are decided here instead, on plain millisecond timestamps,
+//! and `tap_hold_keys.rs` does the rest on the firmware.
+
+/// Tap-hold settings of a key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Profile {
+    /// Still down after this long, the key is held
+    pub hold_timeout_ms: u64,
+    /// Pressed again this soon after a tap, the key taps right away, so that
+    /// holding it repeats the tap
+    pub quick_tap_ms: u64,
+    /// Another key tapped while this one is down makes it a hold
+    pub permissive_hold: bool,
+    /// Another key pressed while this one is down makes it a hold
+    pub hold_on_other_press: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Tap,
+    Hold,
+}
+
+/// Keys pressed after the tap-hold key that are remembered for permissive hold
+const MAX_OTHER_KEYS: usize = 8;
+
+/// Other keys that went down while a key is down, to tell whether they were
+/// tapped inside it
+#[derive(Clone, Debug, Default)]
+pub struct Interrupts {
+    /// As `(row, col)`
+    keys: [Option<(u8, u8)>; MAX_OTHER_KEYS],
+}
+
+impl Interrupts {
+    pub fn press(&mut self, key: (u8, u8)) {
+        if let Some(slot) = self.keys.iter_mut().find(|k| k.is_none()) {
+            *slot = Some(key);
+        }
+    }
+
+    /// Whether the released `key` went down inside
+    pub fn release(&mut self, key: (u8, u8)) -> bool {
+        let Some(slot) = self.keys.iter_mut().find(|k| **k == Some(key)) else {
+            return false;
+        };
+        *slot = None;
+        true
+    }
+}
+
+/// A tap-hold key that is down and not decided yet
+#[derive(Clone, Debug)]
+pub struct Pending {
+    profile: Profile,
+    pressed_at: u64,
+    others: Interrupts,
+}
+
+impl Pending {
+    pub fn new(profile: Profile, now: u64) -> Self {
+        Self {
+            profile,
+            pressed_at: now,
+            others: Interrupts::default(),
+        }
+    }
+
+    /// When the key counts as held
+    pub fn deadline(&self) -> u64 {
+        self.pressed_at + self.profile.hold_timeout_ms
+    }
+
+    /// The decision once time has passed, if any
+    pub fn timeout(&self, now: u64) -> Option<Decision> {
+        (now >= self.deadline()).then_some(Decision::Hold)
+    }
+
+    /// The decision after another key was pressed or released, if any
+    pub fn other_key(&mut self, key: (u8, u8), pressed: bool) -> Option<Decision> {
+        if pressed {
+            if self.profile.hold_on_other_press {
+                return Some(Decision::Hold);
+            }
+            self.others.press(key);
+            return None;
+        }
+        (self.others.release(key) && self.profile.permissive_hold).then_some(Decision::Hold)
+    }
+
+    /// The decision when the key is released before anything else decided it
+    pub fn release(&self) -> Decision {
+        Decision::Tap
+    }
+}
+
+/// Whether a press at `now` comes soon enough after the tap that ended at
+/// `last_tap` to tap again right away
+pub fn is_quick_tap(profile: &Profile, last_tap: Option<u64>, now: u64) -> bool {
+    last_tap.is_some_and(|t| now.saturating_sub(t) < profile.quick_tap_ms)
+}
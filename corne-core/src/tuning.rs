@@ -0,0 +1,121 @@
+//! Answering joystick tuning requests, see `protocol.rs` for the wire format.
+//!
+//! The firmware hands every report of the VIA raw HID interface to its
+//! `Tuner` first, and only those that aren't on `CHANNEL_JOYSTICK` go on to
+//! rmk. corne-tool's mock keyboard answers with a `Tuner` as well.
+//!
+//! Each half only knows its own stick, the other one isn't synced over the
+//! split link, so requests for it are rejected with `Error::InvalidStick`.
+
+use crate::protocol::{
+    Error, JoystickParams, RawSample, Request, Response, Value, ValueId, CHANNEL_JOYSTICK,
+    CMD_GET_VALUE, CMD_SET_VALUE, NUM_STICKS, REPORT_SIZE,
+};
+
+/// Parameters, latest readings and streaming state of the sticks
+pub struct Tuner {
+    /// The sticks that were added, only those are tuned
+    present: [bool; NUM_STICKS],
+    params: [JoystickParams; NUM_STICKS],
+    samples: [RawSample; NUM_STICKS],
+    streaming: [bool; NUM_STICKS],
+}
+
+impl Default for Tuner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tuner {
+    pub const fn new() -> Self {
+        Self {
+            present: [false; NUM_STICKS],
+            params: [JoystickParams::DEFAULT; NUM_STICKS],
+            samples: [RawSample { x: 0, y: 0, seq: 0 }; NUM_STICKS],
+            streaming: [false; NUM_STICKS],
+        }
+    }
+
+    /// A stick that is read here, with its initial parameters
+    pub fn add_stick(&mut self, stick: usize, params: JoystickParams) {
+        self.present[stick] = true;
+        self.params[stick] = params;
+    }
+
+    /// Current parameters of a stick
+    pub fn params(&self, stick: usize) -> JoystickParams {
+        self.params[stick]
+    }
+
+    /// Replace the parameters of a stick
+    pub fn set_params(&mut self, stick: usize, params: JoystickParams) {
+        self.params[stick] = params;
+    }
+
+    /// Record a raw reading, with the report to push to the host if it's streaming
+    pub fn record_sample(&mut self, stick: usize, x: i16, y: i16) -> Option<[u8; REPORT_SIZE]> {
+        let seq = self.samples[stick].seq.wrapping_add(1);
+        self.samples[stick] = RawSample { x, y, seq };
+        self.streaming[stick].then(|| {
+            Response::Value {
+                stick: stick as u8,
+                value: Value::RawAdc(self.samples[stick]),
+            }
+            .encode()
+        })
+    }
+
+    /// The answer to a raw HID report, `None` if it's not on the joystick
+    /// channel and is left to VIA
+    pub fn handle_report(&mut self, report: &[u8; REPORT_SIZE]) -> Option<[u8; REPORT_SIZE]> {
+        if !matches!(report[0], CMD_SET_VALUE | CMD_GET_VALUE) || report[1] != CHANNEL_JOYSTICK {
+            return None;
+        }
+        let response = match Request::decode(report) {
+            Ok(request) => self.handle(request),
+            Err(e) => Response::Error(e),
+        };
+        Some(response.encode())
+    }
+
+    pub fn handle(&mut self, request: Request) -> Response {
+        let stick = match request {
+            Request::Get { stick, .. } | Request::Set { stick, .. } => stick,
+        };
+        if self.present.get(stick as usize) != Some(&true) {
+            return Response::Error(Error::InvalidStick);
+        }
+        match request {
+            Request::Get { stick, id } => {
+                let i = stick as usize;
+                let value = match id {
+                    ValueId::RawAdc => Value::RawAdc(self.samples[i]),
+                    ValueId::Stream => Value::Stream(self.streaming[i]),
+                    _ => match self.params[i].get(id) {
+                        Some(v) => v,
+                        None => return Response::Error(Error::UnknownValue),
+                    },
+                };
+                Response::Value { stick, value }
+            }
+            Request::Set { stick, value } => {
+                let i = stick as usize;
+                match value {
+                    Value::RawAdc(_) => return Response::Error(Error::ReadOnly),
+                    Value::Stream(v) => {
+                        self.streaming[i] = v;
+                        return Response::Value { stick, value };
+                    }
+                    _ => {
+                        self.params[i].set(value);
+                    }
+                }
+                match self.params[i].get(value.id()) {
+                    Some(value) => Response::Value { stick, value },
+                    None => Response::Error(Error::UnknownValue),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Typing any Unicode character, using one of the input methods that the host OS offers.
+//!
+//! A character becomes a sequence of keyboard reports, which are sent as they are.
+//! Nothing in here depends on rmk.
+
+use crate::usage::{self, MOD_LALT, MOD_LCTRL, MOD_LSHIFT, MOD_RALT};
+
+/// How the host turns key presses into a Unicode character
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Method {
+    /// IBus and GTK: `Ctrl+Shift+U`, hex digits, `Enter`
+    Linux = 0,
+    /// macOS with the "Unicode Hex Input" source: hex digits of each UTF-16 unit while `Option` is held
+    MacOs = 1,
+    /// WinCompose: the compose key (`Right Alt`), `U`, hex digits, `Enter`
+    WinCompose = 2,
+    /// Windows without anything installed: decimal digits on the keypad while `Alt` is held.
+    ///
+    /// Needs Num Lock. Characters above U+00FF only work in applications that accept them.
+    AltCode = 3,
+}
+
+impl Method {
+    pub const ALL: [Method; 4] = [
+        Method::Linux,
+        Method::MacOs,
+        Method::WinCompose,
+        Method::AltCode,
+    ];
+
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Self::ALL.get(v as usize).copied()
+    }
+
+    /// The method after this one, for a key that cycles through them
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// One keyboard report: the held modifiers and at most one key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub modifiers: u8,
+    pub key: u8,
+}
+
+/// Room for the longest sequence of any method, WinCompose with six hex digits takes 18
+pub const MAX_SEQUENCE_LEN: usize = 20;
+
+/// The reports that type one character, ending with everything released
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sequence {
+    reports: [Report; MAX_SEQUENCE_LEN],
+    len: usize,
+}
+
+impl Sequence {
+    fn new() -> Self {
+        Self {
+            reports: [Report::default(); MAX_SEQUENCE_LEN],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, modifiers: u8, key: u8) {
+        self.reports[self.len] = Report { modifiers, key };
+        self.len += 1;
+    }
+
+    /// Press and release `key` while `modifiers` are held
+    fn tap(&mut self, modifiers: u8, key: u8) {
+        self.push(modifiers, key);
+        self.push(modifiers, usage::NONE);
+    }
+
+    /// Tap the hex digits of `value`, `min_digits` at least
+    fn hex(&mut self, modifiers: u8, value: u32, min_digits: usize) {
+        let digits = (8 - value.leading_zeros() as usize / 4).max(min_digits);
+        for i in (0..digits).rev() {
+            self.tap(modifiers, usage::hex_digit((value >> (4 * i)) as u8 & 0xF));
+        }
+    }
+
+    pub fn as_slice(&self) -> &[Report] {
+        &self.reports[..self.len]
+    }
+}
+
+/// Reports that type `c` with `method`
+pub fn sequence(method: Method, c: char) -> Sequence {
+    let mut seq = Sequence::new();
+    let code = c as u32;
+    match method {
+        Method::Linux => {
+            seq.tap(MOD_LCTRL | MOD_LSHIFT, usage::U);
+            seq.push(0, usage::NONE);
+            seq.hex(0, code, 1);
+            seq.tap(0, usage::ENTER);
+        }
+        Method::MacOs => {
+            let mut units = [0; 2];
+            for &unit in c.encode_utf16(&mut units).iter() {
+                seq.hex(MOD_LALT, u32::from(unit), 4);
+            }
+            seq.push(0, usage::NONE);
+        }
+        Method::WinCompose => {
+            seq.push(MOD_RALT, usage::NONE);
+            seq.push(0, usage::NONE);
+            seq.tap(0, usage::U);
+            seq.hex(0, code, 1);
+            seq.tap(0, usage::ENTER);
+        }
+        Method::AltCode => {
+            // A leading zero selects the ANSI code page, which matches Latin-1 for these
+            if code <= 0xFF {
+                seq.tap(MOD_LALT, usage::KP0);
+            }
+            let mut digits = [0u8; 7];
+            let mut len = 0;
+            let mut rest = code;
+            loop {
+                digits[len] = (rest % 10) as u8;
+                len += 1;
+                rest /= 10;
+                if rest == 0 {
+                    break;
+                }
+            }
+            for &d in digits[..len].iter().rev() {
+                seq.tap(MOD_LALT, usage::keypad_digit(d));
+            }
+            seq.push(0, usage::NONE);
+        }
+    }
+    seq
+}
@@ -0,0 +1,71 @@
+//! HID keyboard usage IDs and modifier bits, for reports that this firmware
+//! builds itself instead of going through the keymap.
+//!
+//! Plain numbers, so that the code using them doesn't depend on rmk.
+
+pub const NONE: u8 = 0x00;
+pub const A: u8 = 0x04;
+pub const E: u8 = 0x08;
+pub const M: u8 = 0x10;
+pub const Q: u8 = 0x14;
+pub const U: u8 = 0x18;
+pub const Y: u8 = 0x1C;
+pub const Z: u8 = 0x1D;
+pub const KC1: u8 = 0x1E;
+pub const KC0: u8 = 0x27;
+pub const ENTER: u8 = 0x28;
+pub const TAB: u8 = 0x2B;
+pub const SPACE: u8 = 0x2C;
+pub const MINUS: u8 = 0x2D;
+pub const EQUAL: u8 = 0x2E;
+pub const LEFT_BRACKET: u8 = 0x2F;
+pub const RIGHT_BRACKET: u8 = 0x30;
+pub const BACKSLASH: u8 = 0x31;
+pub const NON_US_HASH: u8 = 0x32;
+pub const SEMICOLON: u8 = 0x33;
+pub const QUOTE: u8 = 0x34;
+pub const GRAVE: u8 = 0x35;
+pub const COMMA: u8 = 0x36;
+pub const DOT: u8 = 0x37;
+pub const SLASH: u8 = 0x38;
+pub const KP1: u8 = 0x59;
+pub const KP0: u8 = 0x62;
+pub const NON_US_BACKSLASH: u8 = 0x64;
+
+pub const MOD_LCTRL: u8 = 0x01;
+pub const MOD_LSHIFT: u8 = 0x02;
+pub const MOD_LALT: u8 = 0x04;
+pub const MOD_RSHIFT: u8 = 0x20;
+pub const MOD_RALT: u8 = 0x40;
+
+/// Usage of a letter, `b'a'..=b'z'` or `b'A'..=b'Z'`
+pub const fn letter(c: u8) -> u8 {
+    A + (c.to_ascii_lowercase() - b'a')
+}
+
+/// Usage of a digit in the number row
+pub const fn digit(d: u8) -> u8 {
+    if d == 0 {
+        KC0
+    } else {
+        KC1 + d - 1
+    }
+}
+
+/// Usage of a digit on the keypad
+pub const fn keypad_digit(d: u8) -> u8 {
+    if d == 0 {
+        KP0
+    } else {
+        KP1 + d - 1
+    }
+}
+
+/// Usage of a hex digit, `A`..`F` above 9
+pub const fn hex_digit(d: u8) -> u8 {
+    if d < 10 {
+        digit(d)
+    } else {
+        A + d - 10
+    }
+}
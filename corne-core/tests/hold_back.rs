@@ -0,0 +1,246 @@
+use corne_core::hold_back::{
+    Events, HoldBack, KeyEvent, OneShotKey, TapDanceKey, TapHoldKey, MAX_DOWN, MAX_HELD_BACK,
+};
+use corne_core::tap_hold::Profile;
+
+const GATE: (u8, u8) = (4, 0);
+const TAP: (u8, u8) = (4, 1);
+const HOLD: (u8, u8) = (4, 2);
+/// The key that holds back others
+const KEY: (u8, u8) = (3, 0);
+const A: (u8, u8) = (0, 1);
+const B: (u8, u8) = (0, 2);
+
+const TAP_HOLD: TapHoldKey = TapHoldKey {
+    profile: Profile {
+        hold_timeout_ms: 200,
+        quick_tap_ms: 150,
+        permissive_hold: false,
+        hold_on_other_press: false,
+    },
+    tap: TAP,
+    hold: HOLD,
+};
+
+fn press(pos: (u8, u8)) -> KeyEvent {
+    KeyEvent::press(pos)
+}
+
+fn release(pos: (u8, u8)) -> KeyEvent {
+    KeyEvent::release(pos)
+}
+
+fn sent(events: Option<Events>) -> Vec<KeyEvent> {
+    events.expect("taken care of").as_slice().to_vec()
+}
+
+/// `KEY` pressed, which turned the hold-back layer on
+fn pressed() -> HoldBack {
+    let mut hold_back = HoldBack::new(GATE);
+    assert!(hold_back
+        .press_tap_hold(KEY, TAP_HOLD, 0)
+        .as_slice()
+        .is_empty());
+    hold_back
+}
+
+#[test]
+fn a_tap_sends_its_hidden_key_then_the_gate() {
+    let mut hold_back = pressed();
+    assert_eq!(
+        sent(hold_back.key_event(release(KEY), false, false, 100)),
+        [press(TAP), release(TAP), press(GATE), release(GATE)]
+    );
+    // rmk reports the gate back, nothing else to do
+    assert_eq!(
+        sent(hold_back.key_event(press(GATE), false, false, 101)),
+        []
+    );
+    assert_eq!(
+        sent(hold_back.key_event(release(GATE), false, false, 101)),
+        []
+    );
+}
+
+#[test]
+fn held_back_keys_are_replayed_in_order_after_the_decision() {
+    let mut hold_back = pressed();
+    assert_eq!(sent(hold_back.key_event(press(A), true, false, 10)), []);
+    assert_eq!(sent(hold_back.key_event(release(A), true, false, 20)), []);
+    assert_eq!(sent(hold_back.key_event(press(B), true, false, 30)), []);
+    assert_eq!(hold_back.deadline(), Some(200));
+    assert_eq!(
+        hold_back.timeout(200).as_slice(),
+        [
+            press(HOLD),
+            press(GATE),
+            release(GATE),
+            press(A),
+            release(A),
+            press(B)
+        ]
+    );
+    assert_eq!(
+        sent(hold_back.key_event(release(KEY), false, false, 300)),
+        [release(HOLD)]
+    );
+}
+
+#[test]
+fn the_event_past_the_queue_decides_and_comes_last() {
+    let mut hold_back = pressed();
+    let queued: Vec<KeyEvent> = (0..MAX_HELD_BACK as u8)
+        .map(|i| KeyEvent {
+            pos: (1, i / 2),
+            pressed: i % 2 == 0,
+        })
+        .collect();
+    for &event in &queued {
+        assert_eq!(sent(hold_back.key_event(event, true, false, 10)), []);
+    }
+    let mut expected = vec![press(HOLD), press(GATE), release(GATE)];
+    expected.extend(&queued);
+    expected.push(press(B));
+    assert_eq!(
+        sent(hold_back.key_event(press(B), true, false, 20)),
+        expected
+    );
+    // Still held by the key itself
+    assert_eq!(
+        sent(hold_back.key_event(release(KEY), false, false, 30)),
+        [release(HOLD)]
+    );
+}
+
+#[test]
+fn late_events_are_replayed_and_a_stray_layer_is_turned_off() {
+    let mut hold_back = pressed();
+    hold_back.key_event(release(KEY), false, false, 100);
+    // On its way to rmk before the gate
+    assert_eq!(
+        sent(hold_back.key_event(press(A), true, false, 101)),
+        [press(A)]
+    );
+    assert_eq!(
+        sent(hold_back.key_event(press(GATE), false, false, 102)),
+        []
+    );
+    // Releases come from the layer the key was pressed on
+    assert_eq!(
+        sent(hold_back.key_event(release(A), true, false, 103)),
+        [release(A)]
+    );
+    // With no gate on its way, the layer is on by mistake
+    assert_eq!(
+        sent(hold_back.key_event(press(B), true, false, 104)),
+        [press(GATE), release(GATE), press(B)]
+    );
+    // Not during a Leader sequence
+    assert!(hold_back.key_event(press(B), true, true, 105).is_none());
+}
+
+#[test]
+fn past_the_keys_that_can_be_down_hidden_keys_are_tapped() {
+    let mut hold_back = HoldBack::new(GATE);
+    for i in 0..=MAX_DOWN as u8 {
+        let key = TapHoldKey {
+            hold: (5, i),
+            ..TAP_HOLD
+        };
+        let now = u64::from(i) * 1000;
+        hold_back.press_tap_hold((3, i), key, now);
+        let expected = if i < MAX_DOWN as u8 {
+            vec![press((5, i)), press(GATE), release(GATE)]
+        } else {
+            vec![press((5, i)), release((5, i)), press(GATE), release(GATE)]
+        };
+        assert_eq!(hold_back.timeout(now + 200).as_slice(), expected);
+    }
+    // The one that was tapped has nothing left to release
+    assert!(hold_back
+        .key_event(release((3, MAX_DOWN as u8)), false, false, 9000)
+        .is_none());
+    for i in 0..MAX_DOWN as u8 {
+        assert_eq!(
+            sent(hold_back.key_event(release((3, i)), false, false, 9000)),
+            [release((5, i))]
+        );
+    }
+}
+
+#[test]
+fn a_one_shot_key_holds_until_the_next_key_is_released() {
+    let hidden = (4, 3);
+    let mut hold_back = HoldBack::new(GATE);
+    hold_back.press_one_shot(
+        KEY,
+        OneShotKey {
+            timeout_ms: 1000,
+            hidden,
+        },
+    );
+    assert!(hold_back.wants_pointer());
+    assert_eq!(
+        sent(hold_back.key_event(release(KEY), false, false, 50)),
+        []
+    );
+    assert_eq!(hold_back.deadline(), Some(1050));
+    assert_eq!(
+        sent(hold_back.key_event(press(A), true, false, 100)),
+        [press(hidden), press(GATE), release(GATE), press(A)]
+    );
+    // The replayed press of `A` was on the layer below
+    assert_eq!(
+        sent(hold_back.key_event(release(A), true, false, 200)),
+        [release(A)]
+    );
+    assert_eq!(
+        sent(hold_back.key_event(release(A), false, false, 201)),
+        [release(hidden)]
+    );
+}
+
+#[test]
+fn a_tap_dance_tapped_twice_taps_twice() {
+    let mut hold_back = HoldBack::new(GATE);
+    let key = TapDanceKey {
+        term_ms: 200,
+        tap: TAP,
+        double_tap: None,
+        hold: HOLD,
+        tap_hold: None,
+    };
+    hold_back.press_tap_dance(KEY, key, 0);
+    assert_eq!(
+        sent(hold_back.key_event(release(KEY), false, false, 50)),
+        []
+    );
+    // The key itself, on the hold-back layer now
+    assert_eq!(sent(hold_back.key_event(press(KEY), true, false, 100)), []);
+    assert_eq!(
+        sent(hold_back.key_event(release(KEY), true, false, 150)),
+        [
+            press(TAP),
+            release(TAP),
+            press(TAP),
+            release(TAP),
+            press(GATE),
+            release(GATE)
+        ]
+    );
+}
+
+#[test]
+fn a_quick_tap_holds_the_tap_right_away() {
+    let mut hold_back = pressed();
+    hold_back.key_event(release(KEY), false, false, 100);
+    assert_eq!(
+        hold_back.press_tap_hold(KEY, TAP_HOLD, 200).as_slice(),
+        [press(GATE), release(GATE), press(TAP)]
+    );
+    assert_eq!(hold_back.deadline(), None);
+    assert_eq!(
+        sent(hold_back.key_event(release(KEY), false, false, 400)),
+        [release(TAP)]
+    );
+}
@@ -0,0 +1,104 @@
+use corne_core::host_layout::{HostLayout, Keystroke, GERMAN, US};
+use corne_core::usage::{self, MOD_LSHIFT, MOD_RALT};
+
+fn printable_ascii() -> impl Iterator<Item = char> {
+    (' '..='~').chain(['\n', '\t'])
+}
+
+#[test]
+fn every_layout_types_all_of_ascii() {
+    for layout in HostLayout::ALL {
+        for c in printable_ascii() {
+            assert!(
+                layout.keystroke(c).is_some(),
+                "{:?} cannot type {:?}",
+                layout,
+                c
+            );
+        }
+    }
+}
+
+#[test]
+fn no_two_characters_share_a_keystroke() {
+    for layout in HostLayout::ALL {
+        let chars: Vec<char> = printable_ascii()
+            .chain(layout.table().characters.iter().map(|(c, _)| *c))
+            .collect();
+        for (i, a) in chars.iter().enumerate() {
+            for b in &chars[i + 1..] {
+                if a != b {
+                    assert_ne!(
+                        layout.keystroke(*a),
+                        layout.keystroke(*b),
+                        "{:?}: {:?} and {:?}",
+                        layout,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn tables_have_no_duplicates_or_letters() {
+    for table in [&US, &GERMAN] {
+        for (i, (c, _)) in table.characters.iter().enumerate() {
+            assert!(!c.is_ascii_alphabetic(), "{:?} belongs to the letters", c);
+            assert!(
+                !table.characters[i + 1..].iter().any(|(d, _)| d == c),
+                "{:?} is in the table twice",
+                c
+            );
+        }
+    }
+}
+
+fn stroke(modifiers: u8, key: u8) -> Option<Keystroke> {
+    Some(Keystroke {
+        modifiers,
+        key,
+        dead: false,
+    })
+}
+
+#[test]
+fn us_layout() {
+    let us = HostLayout::Us;
+    assert_eq!(us.keystroke('a'), stroke(0, usage::A));
+    assert_eq!(us.keystroke('Z'), stroke(MOD_LSHIFT, usage::Z));
+    assert_eq!(us.keystroke('$'), stroke(MOD_LSHIFT, usage::digit(4)));
+    assert_eq!(us.keystroke('{'), stroke(MOD_LSHIFT, usage::LEFT_BRACKET));
+    assert_eq!(us.keystroke('ä'), None);
+}
+
+#[test]
+fn german_layout() {
+    let de = HostLayout::German;
+    assert_eq!(de.keystroke('z'), stroke(0, usage::Y));
+    assert_eq!(de.keystroke('Y'), stroke(MOD_LSHIFT, usage::Z));
+    assert_eq!(de.keystroke('@'), stroke(MOD_RALT, usage::Q));
+    assert_eq!(de.keystroke('{'), stroke(MOD_RALT, usage::digit(7)));
+    assert_eq!(de.keystroke('|'), stroke(MOD_RALT, usage::NON_US_BACKSLASH));
+    assert_eq!(de.keystroke('ä'), stroke(0, usage::QUOTE));
+    assert_eq!(de.keystroke('ß'), stroke(0, usage::MINUS));
+    assert_eq!(
+        de.keystroke('^'),
+        Some(Keystroke {
+            modifiers: 0,
+            key: usage::GRAVE,
+            dead: true,
+        })
+    );
+}
+
+#[test]
+fn layout_ids_round_trip() {
+    for layout in HostLayout::ALL {
+        assert_eq!(HostLayout::from_u8(layout as u8), Some(layout));
+    }
+    assert_eq!(HostLayout::from_u8(HostLayout::ALL.len() as u8), None);
+    assert_eq!(HostLayout::German.next(), HostLayout::Us);
+}
@@ -0,0 +1,38 @@
+use corne_core::layer_rules::{resolve, Rule};
+
+const LOWER: u32 = 1 << 1;
+const UPPER: u32 = 1 << 2;
+const ADJUST: u32 = 1 << 3;
+
+const TRI_LAYER: Rule = Rule {
+    when: LOWER | UPPER,
+    then: 3,
+};
+
+#[test]
+fn needs_all_layers() {
+    assert_eq!(resolve(&[TRI_LAYER], 0), 0);
+    assert_eq!(resolve(&[TRI_LAYER], LOWER), 0);
+    assert_eq!(resolve(&[TRI_LAYER], UPPER), 0);
+    assert_eq!(resolve(&[TRI_LAYER], LOWER | UPPER), ADJUST);
+}
+
+#[test]
+fn layers_already_on_stay_as_they_are() {
+    assert_eq!(resolve(&[TRI_LAYER], LOWER | UPPER | ADJUST), 0);
+}
+
+#[test]
+fn later_rules_see_earlier_ones() {
+    let rules = [
+        TRI_LAYER,
+        Rule {
+            when: ADJUST | 1 << 4,
+            then: 5,
+        },
+    ];
+    assert_eq!(resolve(&rules, LOWER | UPPER | 1 << 4), ADJUST | 1 << 5);
+    // Not the other way around
+    let rules = [rules[1], rules[0]];
+    assert_eq!(resolve(&rules, LOWER | UPPER | 1 << 4), ADJUST);
+}
@@ -0,0 +1,64 @@
+use corne_core::leader::{build, BuildError, Leader, Node, Step};
+
+const G: u16 = 10;
+const S: u16 = 22;
+const U: u16 = 24;
+const A: u16 = 4;
+
+fn trie(sequences: &[&[u16]]) -> Vec<Node> {
+    let mut nodes = vec![Node::EMPTY; 16];
+    let used = build(sequences, &mut nodes).unwrap();
+    nodes.truncate(used);
+    nodes
+}
+
+#[test]
+fn sequences_share_their_start() {
+    let nodes = trie(&[&[G, S], &[G, A], &[U, A]]);
+    // The root, G, S, A, U, A
+    assert_eq!(nodes.len(), 6);
+
+    let mut leader = Leader::new(&nodes);
+    assert_eq!(leader.key(G), Step::Pending);
+    assert_eq!(leader.key(A), Step::Done(1));
+
+    let mut leader = Leader::new(&nodes);
+    assert_eq!(leader.key(U), Step::Pending);
+    assert_eq!(leader.key(A), Step::Done(2));
+}
+
+#[test]
+fn unknown_keys_fail() {
+    let nodes = trie(&[&[G, S]]);
+    let mut leader = Leader::new(&nodes);
+    assert_eq!(leader.key(S), Step::Failed);
+
+    let mut leader = Leader::new(&nodes);
+    assert_eq!(leader.key(G), Step::Pending);
+    assert_eq!(leader.key(G), Step::Failed);
+}
+
+#[test]
+fn a_prefix_of_a_longer_sequence_waits_for_the_timeout() {
+    let nodes = trie(&[&[G], &[G, S]]);
+    let mut leader = Leader::new(&nodes);
+    assert_eq!(leader.timeout(), None);
+    assert_eq!(leader.key(G), Step::Pending);
+    assert_eq!(leader.timeout(), Some(0));
+    assert_eq!(leader.key(S), Step::Done(1));
+}
+
+#[test]
+fn invalid_tables_are_rejected() {
+    let mut nodes = vec![Node::EMPTY; 16];
+    assert_eq!(build(&[&[G], &[]], &mut nodes), Err(BuildError::Empty(1)));
+    assert_eq!(
+        build(&[&[G, S], &[U], &[G, S]], &mut nodes),
+        Err(BuildError::Duplicate(2))
+    );
+    let mut nodes = vec![Node::EMPTY; 3];
+    assert_eq!(
+        build(&[&[G, S, A]], &mut nodes),
+        Err(BuildError::TooManyNodes)
+    );
+}
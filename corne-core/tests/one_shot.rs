@@ -0,0 +1,45 @@
+use corne_core::one_shot::{Decision, OneShot};
+
+const TIMEOUT: u64 = 1000;
+
+#[test]
+fn tapped_applies_to_the_next_key() {
+    let mut one_shot = OneShot::new(TIMEOUT);
+    assert_eq!(one_shot.deadline(), None);
+    one_shot.release(100);
+    assert!(one_shot.is_armed());
+    assert_eq!(one_shot.other_key(false), None);
+    assert_eq!(one_shot.other_key(true), Some(Decision::NextKey));
+}
+
+#[test]
+fn held_works_as_a_plain_key() {
+    let one_shot = OneShot::new(TIMEOUT);
+    assert_eq!(one_shot.timeout(5000), None);
+    assert_eq!(one_shot.other_key(true), Some(Decision::Hold));
+}
+
+#[test]
+fn times_out_after_the_tap() {
+    let mut one_shot = OneShot::new(TIMEOUT);
+    one_shot.release(100);
+    assert_eq!(one_shot.deadline(), Some(1100));
+    assert_eq!(one_shot.timeout(1099), None);
+    assert_eq!(one_shot.timeout(1100), Some(Decision::Cancel));
+}
+
+#[test]
+fn pressed_again_takes_it_back() {
+    let mut one_shot = OneShot::new(TIMEOUT);
+    assert_eq!(one_shot.press(), None);
+    one_shot.release(100);
+    assert_eq!(one_shot.press(), Some(Decision::Cancel));
+}
+
+#[test]
+fn the_pointer_clears_it_unless_held() {
+    let mut one_shot = OneShot::new(TIMEOUT);
+    assert_eq!(one_shot.pointer(), Decision::Hold);
+    one_shot.release(100);
+    assert_eq!(one_shot.pointer(), Decision::Cancel);
+}
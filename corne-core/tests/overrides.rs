@@ -0,0 +1,56 @@
+use corne_core::overrides::LayerOverride;
+use corne_core::repeat::Keystroke;
+use corne_core::usage::{self, MOD_LCTRL, MOD_LSHIFT, MOD_RSHIFT};
+
+const ESCAPE: u8 = 0x29;
+const MOD_RCTRL: u8 = 0x10;
+
+fn key(key: u8, modifiers: u8) -> Keystroke {
+    Keystroke { key, modifiers }
+}
+
+/// Shift+Comma types a semicolon on the base layer
+fn semicolon() -> LayerOverride {
+    LayerOverride {
+        layers: 1 << 0,
+        trigger: usage::COMMA,
+        output: usage::SEMICOLON,
+        modifiers: MOD_LSHIFT | MOD_RSHIFT,
+        keep_modifiers: false,
+    }
+}
+
+#[test]
+fn the_triggering_modifiers_are_released_for_the_output() {
+    let o = semicolon();
+    assert_eq!(o.keystroke(0, MOD_LSHIFT), key(usage::SEMICOLON, 0));
+    assert_eq!(o.keystroke(0, MOD_RSHIFT), key(usage::SEMICOLON, 0));
+    // Only those of the override
+    assert_eq!(
+        o.keystroke(0, MOD_LSHIFT | MOD_LCTRL),
+        key(usage::SEMICOLON, MOD_LCTRL)
+    );
+}
+
+#[test]
+fn other_layers_type_the_trigger_with_the_modifiers_that_are_down() {
+    let o = semicolon();
+    assert_eq!(o.keystroke(1, MOD_LSHIFT), key(usage::COMMA, MOD_LSHIFT));
+    assert_eq!(
+        o.keystroke(3, MOD_RSHIFT | MOD_LCTRL),
+        key(usage::COMMA, MOD_RSHIFT | MOD_LCTRL)
+    );
+}
+
+#[test]
+fn kept_modifiers_stay_down() {
+    let o = LayerOverride {
+        layers: 1 << 0 | 1 << 2,
+        trigger: ESCAPE,
+        output: usage::GRAVE,
+        modifiers: MOD_LCTRL | MOD_RCTRL,
+        keep_modifiers: true,
+    };
+    assert_eq!(o.keystroke(2, MOD_RCTRL), key(usage::GRAVE, MOD_RCTRL));
+    assert_eq!(o.keystroke(1, MOD_RCTRL), key(ESCAPE, MOD_RCTRL));
+}
@@ -0,0 +1,49 @@
+use corne_core::repeat::{alternate, Keystroke};
+use corne_core::usage;
+
+const UP: u8 = 0x52;
+const DOWN: u8 = 0x51;
+const LEFT: u8 = 0x50;
+
+fn key(key: u8, modifiers: u8) -> Keystroke {
+    Keystroke { key, modifiers }
+}
+
+#[test]
+fn pairs_go_both_ways() {
+    let pairs = [(key(UP, 0), key(DOWN, 0))];
+    assert_eq!(alternate(&pairs, key(UP, 0)), Some(key(DOWN, 0)));
+    assert_eq!(alternate(&pairs, key(DOWN, 0)), Some(key(UP, 0)));
+    assert_eq!(alternate(&pairs, key(LEFT, 0)), None);
+}
+
+#[test]
+fn other_modifiers_stay() {
+    let pairs = [(key(UP, 0), key(DOWN, 0))];
+    assert_eq!(
+        alternate(&pairs, key(UP, usage::MOD_LCTRL)),
+        Some(key(DOWN, usage::MOD_LCTRL))
+    );
+}
+
+#[test]
+fn shifted_pairs_win_over_plain_ones() {
+    let nine = usage::digit(9);
+    let zero = usage::digit(0);
+    let pairs = [
+        (key(nine, 0), key(zero, 0)),
+        // ( and ) on a US host
+        (key(nine, usage::MOD_LSHIFT), key(zero, usage::MOD_LSHIFT)),
+        (key(usage::LEFT_BRACKET, 0), key(usage::RIGHT_BRACKET, 0)),
+    ];
+    assert_eq!(
+        alternate(&pairs, key(nine, usage::MOD_LSHIFT)),
+        Some(key(zero, usage::MOD_LSHIFT))
+    );
+    // Needs all of the pair's modifiers
+    assert_eq!(alternate(&pairs, key(nine, 0)), Some(key(zero, 0)));
+    assert_eq!(
+        alternate(&pairs, key(usage::RIGHT_BRACKET, usage::MOD_LSHIFT)),
+        Some(key(usage::LEFT_BRACKET, usage::MOD_LSHIFT))
+    );
+}
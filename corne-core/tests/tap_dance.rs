@@ -0,0 +1,64 @@
+use corne_core::tap_dance::{Dance, Decision};
+
+const TERM: u64 = 200;
+
+#[test]
+fn single_tap_waits_for_the_term() {
+    let mut dance = Dance::new(TERM, 0);
+    assert_eq!(dance.release(50), None);
+    assert_eq!(dance.deadline(), 250);
+    assert_eq!(dance.timeout(249), None);
+    assert_eq!(dance.timeout(250), Some(Decision::Tap));
+}
+
+#[test]
+fn double_tap_is_decided_on_the_second_release() {
+    let mut dance = Dance::new(TERM, 0);
+    assert_eq!(dance.release(50), None);
+    dance.press(100);
+    assert_eq!(dance.release(150), Some(Decision::DoubleTap));
+}
+
+#[test]
+fn hold() {
+    let dance = Dance::new(TERM, 0);
+    assert_eq!(dance.timeout(199), None);
+    assert_eq!(dance.timeout(200), Some(Decision::Hold));
+}
+
+#[test]
+fn tap_then_hold() {
+    let mut dance = Dance::new(TERM, 0);
+    assert_eq!(dance.release(50), None);
+    dance.press(100);
+    assert_eq!(dance.deadline(), 300);
+    assert_eq!(dance.timeout(300), Some(Decision::TapHold));
+}
+
+#[test]
+fn another_key_after_a_tap_ends_the_dance() {
+    let mut dance = Dance::new(TERM, 0);
+    assert_eq!(dance.release(50), None);
+    assert_eq!(dance.other_key((1, 1), true), Some(Decision::Tap));
+}
+
+#[test]
+fn another_key_tapped_inside_makes_it_a_hold() {
+    let mut dance = Dance::new(TERM, 0);
+    assert_eq!(dance.other_key((1, 1), true), None);
+    assert_eq!(dance.other_key((1, 1), false), Some(Decision::Hold));
+
+    let mut dance = Dance::new(TERM, 0);
+    assert_eq!(dance.release(50), None);
+    dance.press(100);
+    assert_eq!(dance.other_key((1, 1), true), None);
+    assert_eq!(dance.other_key((1, 1), false), Some(Decision::TapHold));
+}
+
+#[test]
+fn rolling_over_stays_a_tap() {
+    let mut dance = Dance::new(TERM, 0);
+    assert_eq!(dance.other_key((1, 1), true), None);
+    assert_eq!(dance.release(50), None);
+    assert_eq!(dance.other_key((1, 1), false), Some(Decision::Tap));
+}
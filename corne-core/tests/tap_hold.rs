@@ -0,0 +1,69 @@
+use corne_core::tap_hold::{is_quick_tap, Decision, Pending, Profile};
+
+const PROFILE: Profile = Profile {
+    hold_timeout_ms: 200,
+    quick_tap_ms: 150,
+    permissive_hold: false,
+    hold_on_other_press: false,
+};
+
+#[test]
+fn released_before_the_timeout_is_a_tap() {
+    let pending = Pending::new(PROFILE, 1000);
+    assert_eq!(pending.timeout(1199), None);
+    assert_eq!(pending.release(), Decision::Tap);
+}
+
+#[test]
+fn held_past_the_timeout_is_a_hold() {
+    let pending = Pending::new(PROFILE, 1000);
+    assert_eq!(pending.deadline(), 1200);
+    assert_eq!(pending.timeout(1200), Some(Decision::Hold));
+}
+
+#[test]
+fn other_keys_only_decide_when_enabled() {
+    let mut pending = Pending::new(PROFILE, 0);
+    assert_eq!(pending.other_key((0, 1), true), None);
+    assert_eq!(pending.other_key((0, 1), false), None);
+}
+
+#[test]
+fn hold_on_other_press() {
+    let profile = Profile {
+        hold_on_other_press: true,
+        ..PROFILE
+    };
+    let mut pending = Pending::new(profile, 0);
+    assert_eq!(pending.other_key((0, 1), true), Some(Decision::Hold));
+}
+
+#[test]
+fn permissive_hold_needs_a_tap_inside() {
+    let profile = Profile {
+        permissive_hold: true,
+        ..PROFILE
+    };
+    let mut pending = Pending::new(profile, 0);
+    // Pressed before the tap-hold key, released inside it
+    assert_eq!(pending.other_key((0, 2), false), None);
+    // Rolled over, the tap-hold key goes up first
+    assert_eq!(pending.other_key((0, 1), true), None);
+    assert_eq!(pending.release(), Decision::Tap);
+
+    let mut pending = Pending::new(profile, 0);
+    assert_eq!(pending.other_key((0, 1), true), None);
+    assert_eq!(pending.other_key((0, 1), false), Some(Decision::Hold));
+}
+
+#[test]
+fn quick_tap_term() {
+    assert!(!is_quick_tap(&PROFILE, None, 1000));
+    assert!(is_quick_tap(&PROFILE, Some(900), 1000));
+    assert!(!is_quick_tap(&PROFILE, Some(850), 1000));
+    let profile = Profile {
+        quick_tap_ms: 0,
+        ..PROFILE
+    };
+    assert!(!is_quick_tap(&profile, Some(1000), 1000));
+}
@@ -0,0 +1,153 @@
+use corne_core::protocol::{
+    Error, JoystickParams, RawSample, Request, Response, Value, ValueId, CMD_GET_VALUE, REPORT_SIZE,
+};
+use corne_core::tuning::Tuner;
+
+/// A tuner that has both sticks
+fn tuner() -> Tuner {
+    let mut tuner = Tuner::new();
+    tuner.add_stick(0, JoystickParams::default());
+    tuner.add_stick(1, JoystickParams::default());
+    tuner
+}
+
+/// Send `request` the way the raw HID endpoint does and decode the answer
+fn exchange(tuner: &mut Tuner, request: Request) -> Response {
+    let report = tuner
+        .handle_report(&request.encode())
+        .expect("a joystick request is answered");
+    Response::decode(&report).unwrap()
+}
+
+#[test]
+fn answers_get_and_set_through_raw_reports() {
+    let mut tuner = tuner();
+    assert_eq!(
+        exchange(
+            &mut tuner,
+            Request::Get {
+                stick: 0,
+                id: ValueId::Gain
+            }
+        ),
+        Response::Value {
+            stick: 0,
+            value: Value::Gain(100)
+        }
+    );
+    // The answer to a set is the value as stored
+    assert_eq!(
+        exchange(
+            &mut tuner,
+            Request::Set {
+                stick: 1,
+                value: Value::Rotation(450)
+            }
+        ),
+        Response::Value {
+            stick: 1,
+            value: Value::Rotation(90)
+        }
+    );
+    assert_eq!(tuner.params(1).rotation, 90);
+    assert_eq!(tuner.params(0).rotation, 0);
+}
+
+#[test]
+fn leaves_other_reports_to_via() {
+    let mut tuner = tuner();
+    // id_get_protocol_version
+    let mut report = [0u8; REPORT_SIZE];
+    report[0] = 0x01;
+    assert_eq!(tuner.handle_report(&report), None);
+    // A custom value of VIA's own backlight channel
+    report[0] = CMD_GET_VALUE;
+    report[1] = 0x01;
+    assert_eq!(tuner.handle_report(&report), None);
+}
+
+#[test]
+fn rejects_bad_requests_on_the_joystick_channel() {
+    let mut tuner = tuner();
+    let mut report = Request::Get {
+        stick: 0,
+        id: ValueId::Gain,
+    }
+    .encode();
+    report[2] = 0x7F;
+    let answer = tuner.handle_report(&report).unwrap();
+    assert_eq!(
+        Response::decode(&answer),
+        Ok(Response::Error(Error::UnknownValue))
+    );
+
+    assert_eq!(
+        exchange(
+            &mut tuner,
+            Request::Set {
+                stick: 0,
+                value: Value::RawAdc(RawSample::default())
+            }
+        ),
+        Response::Error(Error::ReadOnly)
+    );
+}
+
+#[test]
+fn streams_samples_once_enabled() {
+    let mut tuner = tuner();
+    assert_eq!(tuner.record_sample(0, 10, -10), None);
+    exchange(
+        &mut tuner,
+        Request::Set {
+            stick: 0,
+            value: Value::Stream(true),
+        },
+    );
+    let report = tuner.record_sample(0, 20, -20).unwrap();
+    assert_eq!(
+        Response::decode(&report),
+        Ok(Response::Value {
+            stick: 0,
+            value: Value::RawAdc(RawSample {
+                x: 20,
+                y: -20,
+                seq: 2
+            })
+        })
+    );
+    // Only the stick that streams
+    assert_eq!(tuner.record_sample(1, 1, 1), None);
+}
+
+#[test]
+fn rejects_sticks_that_were_not_added() {
+    // The central, without the stick of the peripheral
+    let mut tuner = Tuner::new();
+    tuner.add_stick(0, JoystickParams::default());
+    let get = |stick| Request::Get {
+        stick,
+        id: ValueId::Gain,
+    };
+    assert!(matches!(
+        exchange(&mut tuner, get(0)),
+        Response::Value { stick: 0, .. }
+    ));
+    for stick in [1, 2] {
+        assert_eq!(
+            exchange(&mut tuner, get(stick)),
+            Response::Error(Error::InvalidStick)
+        );
+    }
+    assert_eq!(
+        exchange(
+            &mut tuner,
+            Request::Set {
+                stick: 1,
+                value: Value::Stream(true)
+            }
+        ),
+        Response::Error(Error::InvalidStick)
+    );
+    assert_eq!(tuner.record_sample(1, 1, 1), None);
+}
@@ -0,0 +1,121 @@
+use corne_core::unicode::{sequence, Method, Report, MAX_SEQUENCE_LEN};
+use corne_core::usage::{self, MOD_LALT, MOD_LCTRL, MOD_LSHIFT, MOD_RALT};
+
+fn report(modifiers: u8, key: u8) -> Report {
+    Report { modifiers, key }
+}
+
+/// Reports that tap each of `keys` while `modifiers` are held
+fn taps(modifiers: u8, keys: &[u8]) -> Vec<Report> {
+    keys.iter()
+        .flat_map(|&key| [report(modifiers, key), report(modifiers, usage::NONE)])
+        .collect()
+}
+
+/// Usages of the hex digits of `digits`
+fn hex(digits: &str) -> Vec<u8> {
+    digits
+        .chars()
+        .map(|d| usage::hex_digit(d.to_digit(16).unwrap() as u8))
+        .collect()
+}
+
+/// Usages of the decimal digits of `digits` on the keypad
+fn keypad(digits: &str) -> Vec<u8> {
+    digits
+        .chars()
+        .map(|d| usage::keypad_digit(d.to_digit(10).unwrap() as u8))
+        .collect()
+}
+
+#[test]
+fn linux_types_ctrl_shift_u_the_hex_digits_and_enter() {
+    let mut expected = taps(MOD_LCTRL | MOD_LSHIFT, &[usage::U]);
+    expected.push(report(0, usage::NONE));
+    expected.extend(taps(0, &hex("e4")));
+    expected.extend(taps(0, &[usage::ENTER]));
+    assert_eq!(sequence(Method::Linux, 'ä').as_slice(), expected);
+}
+
+#[test]
+fn macos_types_four_hex_digits_while_option_is_held() {
+    let mut expected = taps(MOD_LALT, &hex("00e4"));
+    expected.push(report(0, usage::NONE));
+    assert_eq!(sequence(Method::MacOs, 'ä').as_slice(), expected);
+}
+
+#[test]
+fn wincompose_taps_the_compose_key_then_u_the_hex_digits_and_enter() {
+    let mut expected = vec![report(MOD_RALT, usage::NONE), report(0, usage::NONE)];
+    expected.extend(taps(0, &[usage::U]));
+    expected.extend(taps(0, &hex("20ac")));
+    expected.extend(taps(0, &[usage::ENTER]));
+    assert_eq!(sequence(Method::WinCompose, '€').as_slice(), expected);
+}
+
+#[test]
+fn alt_codes_of_latin_1_start_with_a_zero() {
+    let mut expected = taps(MOD_LALT, &keypad("0228"));
+    expected.push(report(0, usage::NONE));
+    assert_eq!(sequence(Method::AltCode, 'ä').as_slice(), expected);
+
+    // U+20AC is 8364, without the zero of the ANSI code page
+    let mut expected = taps(MOD_LALT, &keypad("8364"));
+    expected.push(report(0, usage::NONE));
+    assert_eq!(sequence(Method::AltCode, '€').as_slice(), expected);
+}
+
+#[test]
+fn characters_above_u_ffff() {
+    let emoji = '\u{1F600}';
+
+    let mut expected = taps(MOD_LCTRL | MOD_LSHIFT, &[usage::U]);
+    expected.push(report(0, usage::NONE));
+    expected.extend(taps(0, &hex("1f600")));
+    expected.extend(taps(0, &[usage::ENTER]));
+    assert_eq!(sequence(Method::Linux, emoji).as_slice(), expected);
+
+    // A surrogate pair, each unit with four digits
+    let mut expected = taps(MOD_LALT, &hex("d83dde00"));
+    expected.push(report(0, usage::NONE));
+    assert_eq!(sequence(Method::MacOs, emoji).as_slice(), expected);
+
+    let mut expected = taps(MOD_LALT, &keypad("128512"));
+    expected.push(report(0, usage::NONE));
+    assert_eq!(sequence(Method::AltCode, emoji).as_slice(), expected);
+}
+
+#[test]
+fn every_character_fits_and_ends_with_everything_released() {
+    let mut longest = 0;
+    for c in (0..=char::MAX as u32).filter_map(char::from_u32) {
+        for method in Method::ALL {
+            let reports = sequence(method, c);
+            let reports = reports.as_slice();
+            assert!(reports.len() <= MAX_SEQUENCE_LEN, "{:?} {:?}", method, c);
+            assert_eq!(
+                reports.last(),
+                Some(&Report::default()),
+                "{:?} {:?}",
+                method,
+                c
+            );
+            longest = longest.max(reports.len());
+        }
+    }
+    // WinCompose with six hex digits
+    assert_eq!(longest, 18);
+}
+
+#[test]
+fn methods_cycle_and_are_stored_as_their_number() {
+    for method in Method::ALL {
+        assert_eq!(Method::from_u8(method as u8), Some(method));
+    }
+    assert_eq!(Method::from_u8(Method::ALL.len() as u8), None);
+    let mut method = Method::Linux;
+    for _ in Method::ALL {
+        method = method.next();
+    }
+    assert_eq!(method, Method::Linux);
+}
@@ -0,0 +1,94 @@
+//! `[[auto_shift]]` blocks, the keys that are shifted when held.
+
+use crate::config::layer_index;
+
+/// Keycodes of an `[[auto_shift]]` key group
+pub(crate) fn auto_shift_group(group: &str) -> Vec<String> {
+    match group {
+        "letters" => ('A'..='Z').map(String::from).collect(),
+        "digits" => (0..10).map(|d| format!("Kc{}", d)).collect(),
+        "symbols" => [
+            "Minus",
+            "Equal",
+            "LeftBracket",
+            "RightBracket",
+            "Backslash",
+            "Semicolon",
+            "Quote",
+            "Grave",
+            "Comma",
+            "Dot",
+            "Slash",
+        ]
+        .map(String::from)
+        .to_vec(),
+        _ => panic!(
+            "Unknown auto-shift group `{}`, there are letters, digits and symbols",
+            group
+        ),
+    }
+}
+
+/// The `[[auto_shift]]` blocks, as the keycodes and the layers, all of them if `None`
+pub(crate) fn auto_shift_rules(
+    config: &toml::Table,
+    layer_names: &[String],
+) -> Vec<(Vec<String>, Option<Vec<usize>>)> {
+    config
+        .get("auto_shift")
+        .map(|a| {
+            a.as_array()
+                .expect("[[auto_shift]] must be an array")
+                .clone()
+        })
+        .unwrap_or_default()
+        .iter()
+        .map(|rule| {
+            let rule = rule.as_table().expect("[[auto_shift]] must be a table");
+            if let Some(key) = rule.keys().find(|k| *k != "keys" && *k != "layers") {
+                panic!("Unknown setting `{}` in [[auto_shift]]", key);
+            }
+            let keys = rule
+                .get("keys")
+                .and_then(|k| k.as_array())
+                .expect("Missing keys in [[auto_shift]]")
+                .iter()
+                .flat_map(|g| auto_shift_group(g.as_str().expect("Auto-shift keys are strings")))
+                .collect();
+            let layers = rule.get("layers").map(|layers| {
+                layers
+                    .as_array()
+                    .expect("Auto-shift layers are a list")
+                    .iter()
+                    .map(|l| {
+                        layer_index(
+                            l.as_str().expect("Auto-shift layers are strings"),
+                            layer_names,
+                        )
+                    })
+                    .collect()
+            });
+            (keys, layers)
+        })
+        .collect()
+}
+
+/// The auto-shifting version of the key with `action` on `layer`, if `[[auto_shift]]`
+/// covers it: an rmk tap-hold key that holds the key with Shift
+pub(crate) fn auto_shift_action(
+    rules: &[(Vec<String>, Option<Vec<usize>>)],
+    action: &str,
+    layer: usize,
+) -> Option<String> {
+    let key = action.strip_prefix("k!(")?.strip_suffix(')')?;
+    rules
+        .iter()
+        .any(|(keys, layers)| {
+            keys.iter().any(|k| k == key) && layers.as_ref().is_none_or(|l| l.contains(&layer))
+        })
+        .then(|| {
+            format!(
+                "KeyAction::TapHold(Action::Key(KeyCode::{key}), Action::KeyWithModifier(KeyCode::{key}, ModifierCombination::new_from(false, false, false, true, false)))"
+            )
+        })
+}
@@ -0,0 +1,164 @@
+//! The `[combos]` block.
+
+use crate::auto_shift::{auto_shift_action, auto_shift_rules};
+use crate::config::{layer_index, parse_duration_ms, rmk_constant};
+use crate::custom_keys::{custom_key_name, is_dispatched};
+use crate::keymap::key_action;
+
+/// Timeout and Rust expressions of the `[[combos.combo]]` blocks, one `Combo` for
+/// each layer that a combo is on.
+///
+/// rmk fires the first combo in the list whose keys are all down, so combos
+/// with more keys go first and win over those with a part of their keys.
+///
+/// rmk matches the keys by action, so each one has to be at a single position of
+/// `keymap`, the actions of every layer, on the layers the combo is on.
+pub(crate) fn combos(
+    config: &toml::Table,
+    layer_names: &[String],
+    keymap: &[Vec<Vec<String>>],
+) -> (u64, String) {
+    let Some(combos) = config.get("combos") else {
+        return (50, String::new());
+    };
+    let timeout = parse_duration_ms(
+        combos
+            .get("timeout")
+            .map(|t| {
+                t.as_str()
+                    .expect("combos.timeout is a string like \"50ms\"")
+            })
+            .unwrap_or("50ms"),
+    );
+    let max_num = rmk_constant(config, "combo_max_num", 8);
+    let max_length = rmk_constant(config, "combo_max_length", 4);
+    let auto_shift = auto_shift_rules(config, layer_names);
+
+    // (keys, output, layer, description)
+    let mut entries: Vec<(Vec<String>, String, Option<usize>, String)> = Vec::new();
+    for combo in combos
+        .get("combo")
+        .and_then(|c| c.as_array())
+        .map_or(&[][..], |c| c.as_slice())
+    {
+        let keys: Vec<&str> = combo
+            .get("keys")
+            .and_then(|k| k.as_array())
+            .expect("Missing keys in [[combos.combo]]")
+            .iter()
+            .map(|k| k.as_str().expect("Combo keys are strings"))
+            .collect();
+        let output = combo
+            .get("output")
+            .and_then(|o| o.as_str())
+            .expect("Missing output in [[combos.combo]]");
+        let description = format!("{} -> {}", keys.join(" + "), output);
+        if keys.len() < 2 || keys.len() > max_length {
+            panic!(
+                "Combo {} needs 2 to {} keys (rmk.combo_max_length)",
+                description, max_length
+            );
+        }
+        // Their keycode is assigned by the build script and changes with the layers
+        if let Some(key) = keys.iter().find(|k| custom_key_name(k).is_some()) {
+            panic!(
+                "Combo {} uses {}, custom keys can't be in combos",
+                description, key
+            );
+        }
+        let actions: Vec<String> = keys.iter().map(|k| key_action(k, layer_names)).collect();
+        let same_keys = |other: &[String]| {
+            other.len() == actions.len() && other.iter().all(|a| actions.contains(a))
+        };
+        // Found by position, which a combo doesn't have
+        if custom_key_name(output).is_some_and(|name| is_dispatched(&name)) {
+            panic!(
+                "Combo {} sends {}, which can't be the output of a combo",
+                description, output
+            );
+        }
+        let output = key_action(output, layer_names);
+        let layers: Vec<Option<usize>> = match combo.get("layers").and_then(|l| l.as_array()) {
+            Some(layers) => layers
+                .iter()
+                .map(|l| {
+                    let name = l.as_str().expect("Combo layers are strings");
+                    Some(layer_index(name, layer_names))
+                })
+                .collect(),
+            None => vec![None],
+        };
+        for layer in layers {
+            // rmk matches combos by action, which auto-shift changes
+            let auto_shifted = actions.iter().find(|a| match layer {
+                Some(layer) => auto_shift_action(&auto_shift, a, layer).is_some(),
+                None => {
+                    (0..layer_names.len()).any(|l| auto_shift_action(&auto_shift, a, l).is_some())
+                }
+            });
+            if let Some(action) = auto_shifted {
+                panic!(
+                    "Combo {} uses {}, which is auto-shifted on its layers",
+                    description, action
+                );
+            }
+            let on_layers = match layer {
+                Some(layer) => layer..layer + 1,
+                None => 0..layer_names.len(),
+            };
+            let keys_and_actions = || keys.iter().zip(&actions);
+            for (layer, (key, action)) in
+                on_layers.flat_map(|l| keys_and_actions().map(move |k| (l, k)))
+            {
+                let positions: Vec<(usize, usize)> = keymap[layer]
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(row, keys)| {
+                        keys.iter()
+                            .enumerate()
+                            .filter(|(_, a)| *a == action)
+                            .map(move |(col, _)| (row, col))
+                    })
+                    .collect();
+                if positions.len() > 1 {
+                    panic!(
+                        "Combo {} uses {}, which is at {:?} on {}, rmk would match each of them",
+                        description, key, positions, layer_names[layer]
+                    );
+                }
+            }
+            let clash = entries.iter().find(|(k, _, l, _)| {
+                same_keys(k) && (l.is_none() || layer.is_none() || *l == layer)
+            });
+            if let Some((.., other)) = clash {
+                panic!(
+                    "Combos {} and {} have the same keys on the same layer",
+                    other, description
+                );
+            }
+            entries.push((actions.clone(), output.clone(), layer, description.clone()));
+        }
+    }
+    if entries.len() > max_num {
+        panic!(
+            "keyboard.toml has {} combos, one per layer they are on, but rmk.combo_max_num is {}",
+            entries.len(),
+            max_num
+        );
+    }
+    // Stable, so combos of the same size keep their order
+    entries.sort_by_key(|e| std::cmp::Reverse(e.0.len()));
+    let code = entries
+        .iter()
+        .map(|(keys, output, layer, description)| {
+            format!(
+                "            // {}\n            Combo::new([{}], {}, {:?}),\n",
+                description,
+                keys.join(", "),
+                output,
+                layer.map(|l| l as u8)
+            )
+        })
+        .collect();
+    (timeout, code)
+}
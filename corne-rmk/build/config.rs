@@ -0,0 +1,244 @@
+//! Reading `keyboard.toml`, and the key names, layer names and durations in it.
+
+use std::fs;
+
+use corne_core::usage;
+
+/// The character of `UC(ä)` or `UC(U+00E4)`
+pub(crate) fn unicode_char(arg: &str) -> char {
+    let mut chars = arg.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return c;
+    }
+    arg.strip_prefix("U+")
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .and_then(char::from_u32)
+        .unwrap_or_else(|| panic!("Invalid character `UC({})` in keyboard.toml", arg))
+}
+
+/// Milliseconds of a duration like `"250ms"` or `"1s"`
+pub(crate) fn parse_duration_ms(value: &str) -> u64 {
+    let value = value.trim();
+    if let Some(ms) = value.strip_suffix("ms") {
+        ms.trim().parse().ok()
+    } else if let Some(s) = value.strip_suffix('s') {
+        s.trim().parse().ok().map(|s: u64| s * 1000)
+    } else {
+        None
+    }
+    .unwrap_or_else(|| panic!("Invalid duration `{}` in keyboard.toml", value))
+}
+
+/// Milliseconds of the `timeout` in the `[<section>]` block, like `[one_shot]`,
+/// which has no settings besides it and `others`
+pub(crate) fn section_timeout_ms(
+    config: &toml::Table,
+    section: &str,
+    others: &[&str],
+    default: &str,
+) -> u64 {
+    let Some(table) = config.get(section).and_then(|s| s.as_table()) else {
+        return parse_duration_ms(default);
+    };
+    if let Some(key) = table
+        .keys()
+        .find(|k| *k != "timeout" && !others.contains(&k.as_str()))
+    {
+        panic!("Unknown setting `{}` in [{}]", key, section);
+    }
+    parse_duration_ms(
+        table
+            .get("timeout")
+            .map(|t| {
+                t.as_str()
+                    .unwrap_or_else(|| panic!("{}.timeout is a string like \"1s\"", section))
+            })
+            .unwrap_or(default),
+    )
+}
+
+pub(crate) fn read_keyboard_toml() -> toml::Table {
+    let content = fs::read_to_string("keyboard.toml").expect("Cannot read keyboard.toml");
+    content.parse().expect("Cannot parse keyboard.toml")
+}
+
+/// Keys that are easier to write as the character they produce
+pub(crate) fn key_alias(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "0" => "Kc0",
+        "1" => "Kc1",
+        "2" => "Kc2",
+        "3" => "Kc3",
+        "4" => "Kc4",
+        "5" => "Kc5",
+        "6" => "Kc6",
+        "7" => "Kc7",
+        "8" => "Kc8",
+        "9" => "Kc9",
+        ";" => "Semicolon",
+        "'" => "Quote",
+        "," => "Comma",
+        "." => "Dot",
+        "/" => "Slash",
+        "\\" => "Backslash",
+        "-" => "Minus",
+        "=" => "Equal",
+        "[" => "LeftBracket",
+        "]" => "RightBracket",
+        "`" => "Grave",
+        _ => return None,
+    })
+}
+
+/// Name of a keycode, e.g. `Q`, `Backspace` or an alias like `;`
+pub(crate) fn keycode(key: &str) -> String {
+    let key = key.trim();
+    if let Some(alias) = key_alias(key) {
+        return alias.to_owned();
+    }
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        panic!("Invalid key `{}` in keyboard.toml", key);
+    }
+    key.to_owned()
+}
+
+/// Split `LT(Layer, Key)` into `("LT", ["Layer", "Key"])`
+pub(crate) fn split_function(token: &str) -> Option<(&str, Vec<&str>)> {
+    let open = token.find('(')?;
+    let args = token[open + 1..].strip_suffix(')')?;
+    Some((&token[..open], args.split(',').map(str::trim).collect()))
+}
+
+/// Split a layer's keys on whitespace, keeping `LT(Layer, Key)` in one piece
+pub(crate) fn tokenize_keys(keys: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for c in keys.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if c.is_whitespace() && depth == 0 {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Names of the `[[layer]]` blocks, their index if they have none
+pub(crate) fn layer_names(config: &toml::Table) -> Vec<String> {
+    let layers = config["layer"].as_array().expect("Missing [[layer]]");
+    layers
+        .iter()
+        .enumerate()
+        .map(|(i, l)| {
+            l.get("name")
+                .and_then(|n| n.as_str())
+                .map(str::to_owned)
+                .unwrap_or_else(|| i.to_string())
+        })
+        .collect()
+}
+
+/// Resolve a layer given by name or number
+pub(crate) fn layer_index(layer: &str, layer_names: &[String]) -> usize {
+    layer
+        .parse()
+        .ok()
+        .or_else(|| layer_names.iter().position(|n| n == layer))
+        .unwrap_or_else(|| panic!("Unknown layer `{}` in keyboard.toml", layer))
+}
+
+/// rmk's name of a key in the host layout tables
+pub(crate) fn usage_keycode(key: u8) -> String {
+    match key {
+        k if (usage::A..=usage::Z).contains(&k) => ((b'A' + k - usage::A) as char).to_string(),
+        k if (usage::KC1..=usage::KC0).contains(&k) => format!("Kc{}", (k - usage::KC1 + 1) % 10),
+        usage::ENTER => "Enter".to_owned(),
+        usage::TAB => "Tab".to_owned(),
+        usage::SPACE => "Space".to_owned(),
+        usage::MINUS => "Minus".to_owned(),
+        usage::EQUAL => "Equal".to_owned(),
+        usage::LEFT_BRACKET => "LeftBracket".to_owned(),
+        usage::RIGHT_BRACKET => "RightBracket".to_owned(),
+        usage::BACKSLASH => "Backslash".to_owned(),
+        usage::NON_US_HASH => "NonusHash".to_owned(),
+        usage::SEMICOLON => "Semicolon".to_owned(),
+        usage::QUOTE => "Quote".to_owned(),
+        usage::GRAVE => "Grave".to_owned(),
+        usage::COMMA => "Comma".to_owned(),
+        usage::DOT => "Dot".to_owned(),
+        usage::SLASH => "Slash".to_owned(),
+        usage::NON_US_BACKSLASH => "NonusBackslash".to_owned(),
+        _ => panic!("No keycode name for HID usage {:#04X}", key),
+    }
+}
+
+/// A value of the `[rmk]` section, which rmk reads at build time, or its default
+pub(crate) fn rmk_constant(config: &toml::Table, key: &str, default: usize) -> usize {
+    config
+        .get("rmk")
+        .and_then(|rmk| rmk.get(key))
+        .and_then(|v| v.as_integer())
+        .map_or(default, |v| v as usize)
+}
+
+/// HID modifier bits of a modifier in `[[override]]`, `Shift` is either Shift key
+pub(crate) fn modifier_bits(name: &str) -> u8 {
+    match name {
+        "LCtrl" => 0x01,
+        "LShift" => 0x02,
+        "LAlt" => 0x04,
+        "LGui" => 0x08,
+        "RCtrl" => 0x10,
+        "RShift" => 0x20,
+        "RAlt" => 0x40,
+        "RGui" => 0x80,
+        "Ctrl" => 0x11,
+        "Shift" => 0x22,
+        "Alt" => 0x44,
+        "Gui" => 0x88,
+        _ => panic!("Unknown modifier `{}` in [[override]]", name),
+    }
+}
+
+/// Parse `(row,col)` pairs of `matrix_map`, one `Vec` per line
+pub(crate) fn parse_matrix_rows(matrix_map: &str) -> Vec<Vec<(usize, usize)>> {
+    matrix_map
+        .lines()
+        .map(|line| {
+            line.split(')')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| {
+                    let (row, col) = s
+                        .trim_start_matches('(')
+                        .split_once(',')
+                        .unwrap_or_else(|| panic!("Invalid matrix_map entry `{}`", s));
+                    (
+                        row.trim().parse().expect("Invalid row in matrix_map"),
+                        col.trim().parse().expect("Invalid col in matrix_map"),
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Parse `(row,col)` pairs of `matrix_map`
+pub(crate) fn parse_matrix_map(matrix_map: &str) -> Vec<(usize, usize)> {
+    parse_matrix_rows(matrix_map)
+        .into_iter()
+        .flatten()
+        .collect()
+}
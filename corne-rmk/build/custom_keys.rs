@@ -0,0 +1,427 @@
+//! Custom keys, rmk's `User` keycodes that the firmware handles itself, and the
+//! hidden keys they send.
+
+use std::path::Path;
+use std::{env, fs};
+
+use crate::config::{
+    keycode, layer_names, parse_matrix_map, read_keyboard_toml, section_timeout_ms, split_function,
+    tokenize_keys, unicode_char,
+};
+use crate::hidden_keys::{hidden_positions, hold_back_gate};
+use crate::layers::{layer_hidden_keys, layer_tables};
+use crate::leader_keys::{
+    is_hidden_leader_output, leader_key, leader_sequences, leader_tables, LEADER,
+};
+use crate::macros::macro_count;
+use crate::overrides::override_tables;
+use crate::repeat_keys::{repeat_key, repeat_tables, REPEAT};
+use crate::tap_hold_keys::{one_shot_key, tap_dance_hidden_keys, tap_dance_key, tap_hold_key};
+
+/// rmk's own custom keycodes, User0..User11 in this order
+pub(crate) const RMK_CUSTOM_KEYCODES: [(&str, &str, &str); 12] = [
+    ("BT0", "Bluetooth Channel 0", "BT0"),
+    ("BT1", "Bluetooth Channel 1", "BT1"),
+    ("BT2", "Bluetooth Channel 2", "BT2"),
+    ("BT3", "Bluetooth Channel 3", "BT3"),
+    ("BT4", "Bluetooth Channel 4", "BT4"),
+    ("BT5", "Bluetooth Channel 5", "BT5"),
+    ("BT6", "Bluetooth Channel 6", "BT6"),
+    ("BT7", "Bluetooth Channel 7", "BT7"),
+    (
+        "NEXT_BT",
+        "Switch to the next Bluetooth channel",
+        "Next\nBT",
+    ),
+    (
+        "PREV_BT",
+        "Switch to the previous Bluetooth channel",
+        "Prev\nBT",
+    ),
+    ("CLR_BT", "Clear bond info for current channel", "Clear\nBT"),
+    (
+        "SWITCH",
+        "Switch default output mode between USB/BLE",
+        "Switch\nOutput",
+    ),
+];
+
+/// rmk has User0..User31
+pub(crate) const NUM_USER_KEYCODES: usize = 32;
+
+/// A key that the firmware handles itself, see `src/custom_keys.rs`.
+pub(crate) struct CustomKey {
+    /// How it is written in keyboard.toml
+    pub(crate) name: String,
+    /// Rust expression of its `CustomKey`
+    pub(crate) action: String,
+    /// Actions it puts on hidden keys of the base layer, as `((row, col), action)`
+    pub(crate) hidden_actions: Vec<((usize, usize), String)>,
+}
+
+/// Custom keys with a `User` keycode of their own, right after rmk's and in
+/// this order, as `(name, title, short name)`. New ones go at the end, keymaps
+/// saved with Vial refer to them by keycode.
+pub(crate) const FIXED_KEYS: [(&str, &str, &str); 14] = [
+    ("BATT", "Type the battery level", "Batt"),
+    (
+        "JOY_NEXT",
+        "Switch the left joystick between pointer, scroll and off",
+        "Joy\nNext",
+    ),
+    ("UC_LINUX", "Unicode input for Linux (IBus)", "UC\nLinux"),
+    (
+        "UC_MAC",
+        "Unicode input for macOS (Unicode Hex Input)",
+        "UC\nmacOS",
+    ),
+    (
+        "UC_WINC",
+        "Unicode input for Windows (WinCompose)",
+        "UC\nWinC",
+    ),
+    ("UC_ALT", "Unicode input with Windows Alt codes", "UC\nAlt"),
+    (
+        "UC_NEXT",
+        "Switch to the next Unicode input method",
+        "UC\nNext",
+    ),
+    ("HL_US", "The host uses a US layout", "Host\nUS"),
+    ("HL_DE", "The host uses a German layout", "Host\nDE"),
+    ("HL_NEXT", "Switch to the next host layout", "Host\nNext"),
+    (
+        "ALT_REPEAT",
+        "Type the counterpart of the last key, see [repeat]",
+        "Alt\nRep",
+    ),
+    (
+        CAPS_WORD,
+        "Caps Word, capital letters until the end of the word",
+        "Caps\nWord",
+    ),
+    (
+        LEADER,
+        "Leader key, followed by a sequence of keys",
+        "Leader",
+    ),
+    (REPEAT, "Type the last key or character again", "Rep"),
+];
+
+/// The `User` keycode of the other custom keys, `UC(..)` and `TEXT(..)`. Tap-hold,
+/// tap-dance and one-shot keys toggle the hold-back layer instead. The firmware
+/// tells them apart by their position.
+pub(crate) const DISPATCHED: (&str, &str, &str) = (
+    "CUSTOM",
+    "A custom key of keyboard.toml, which one depends on its position",
+    "Custom",
+);
+
+const _: () = assert!(RMK_CUSTOM_KEYCODES.len() + FIXED_KEYS.len() < NUM_USER_KEYCODES);
+
+/// Custom keys in `FIXED_KEYS` that need no settings, as `(name, action)`
+pub(crate) const FIRMWARE_KEYS: [(&str, &str); 11] = [
+    ("BATT", "CustomKey::Battery"),
+    ("JOY_NEXT", "CustomKey::NextJoystickRole"),
+    (
+        "UC_LINUX",
+        "CustomKey::UnicodeMethod(unicode::Method::Linux)",
+    ),
+    ("UC_MAC", "CustomKey::UnicodeMethod(unicode::Method::MacOs)"),
+    (
+        "UC_WINC",
+        "CustomKey::UnicodeMethod(unicode::Method::WinCompose)",
+    ),
+    (
+        "UC_ALT",
+        "CustomKey::UnicodeMethod(unicode::Method::AltCode)",
+    ),
+    ("UC_NEXT", "CustomKey::NextUnicodeMethod"),
+    ("HL_US", "CustomKey::HostLayout(HostLayout::Us)"),
+    ("HL_DE", "CustomKey::HostLayout(HostLayout::German)"),
+    ("HL_NEXT", "CustomKey::NextHostLayout"),
+    ("ALT_REPEAT", "CustomKey::AltRepeat"),
+];
+
+/// Name of the custom key that `token` in a layer stands for
+pub(crate) fn custom_key_name(token: &str) -> Option<String> {
+    if FIXED_KEYS.iter().any(|(name, ..)| *name == token) {
+        return Some(token.to_owned());
+    }
+    let (function, args) = split_function(token)?;
+    match (function, args.as_slice()) {
+        ("UC", [arg]) => Some(format!("UC({})", unicode_char(arg))),
+        ("TEXT", [name]) => Some(format!("TEXT({})", name)),
+        ("TD", [name]) => Some(format!("TD({})", name)),
+        ("OSM", [modifier]) => Some(format!("OSM({})", keycode(modifier))),
+        ("OSL", [layer]) => Some(format!("OSL({})", layer)),
+        ("UC" | "TEXT" | "TD" | "OSM" | "OSL", _) => {
+            panic!("Invalid key `{}` in keyboard.toml", token)
+        }
+        // Those without a profile are rmk's
+        ("TH", [tap, hold, profile]) => Some(format!("TH({},{},{})", tap, hold, profile)),
+        ("LT", [layer, key, profile]) => Some(format!("LT({},{},{})", layer, key, profile)),
+        _ => None,
+    }
+}
+
+/// The custom key called `name`
+pub(crate) fn custom_key(name: &str, config: &toml::Table) -> CustomKey {
+    if let Some(&(name, action)) = FIRMWARE_KEYS.iter().find(|(n, _)| *n == name) {
+        return CustomKey {
+            name: name.to_owned(),
+            action: action.to_owned(),
+            hidden_actions: Vec::new(),
+        };
+    }
+    if let Some(c) = name
+        .strip_prefix("UC(")
+        .and_then(|n| n.strip_suffix(')'))
+        .and_then(|c| c.chars().next())
+    {
+        return CustomKey {
+            name: name.to_owned(),
+            action: format!("CustomKey::Unicode('\\u{{{:x}}}')", c as u32),
+            hidden_actions: Vec::new(),
+        };
+    }
+    let text_name = &name["TEXT(".len()..name.len() - 1];
+    let text = config
+        .get("text")
+        .and_then(|t| t.as_array())
+        .and_then(|texts| {
+            texts
+                .iter()
+                .find(|t| t.get("name").and_then(|n| n.as_str()) == Some(text_name))
+        })
+        .and_then(|t| t.get("text"))
+        .and_then(|t| t.as_str())
+        .unwrap_or_else(|| panic!("No [[text]] called `{}` in keyboard.toml", text_name));
+    CustomKey {
+        name: name.to_owned(),
+        action: format!("CustomKey::Text({:?})", text),
+        hidden_actions: Vec::new(),
+    }
+}
+
+/// Types capital letters until the word ends, see `src/caps_word.rs`
+pub(crate) const CAPS_WORD: &str = "CAPS_WORD";
+
+/// The custom key of `CAPS_WORD`, whose Caps Lock goes on a hidden key from `next_hidden`
+pub(crate) fn caps_word_key(
+    config: &toml::Table,
+    next_hidden: &mut dyn FnMut() -> (usize, usize),
+) -> CustomKey {
+    let caps_lock = next_hidden();
+    CustomKey {
+        name: CAPS_WORD.to_owned(),
+        action: format!(
+            "CustomKey::CapsWord(CapsWordKey {{ timeout_ms: {}, caps_lock: {:?} }})",
+            section_timeout_ms(config, "caps_word", &[], "5s"),
+            caps_lock
+        ),
+        hidden_actions: vec![(caps_lock, "k!(CapsLock)".to_owned())],
+    }
+}
+
+/// Hidden keys that the custom keys in the layers need, see `hidden_positions()`
+pub(crate) fn hidden_keys_needed(config: &toml::Table) -> usize {
+    let mut names: Vec<String> = Vec::new();
+    let layers = config["layer"].as_array().expect("Missing [[layer]]");
+    for layer in layers {
+        let keys_str = layer["keys"].as_str().expect("Missing keys in [[layer]]");
+        for token in tokenize_keys(keys_str) {
+            if let Some(name) = custom_key_name(&token) {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    let needed: usize = names
+        .iter()
+        .map(|name| {
+            if name.starts_with("TH(") || name.starts_with("LT(") {
+                2
+            } else if let Some(dance) = name.strip_prefix("TD(") {
+                tap_dance_hidden_keys(&dance[..dance.len() - 1], config)
+            } else if name.starts_with("OSM(") || name.starts_with("OSL(") || name == CAPS_WORD {
+                1
+            } else if name == LEADER {
+                leader_sequences(config)
+                    .iter()
+                    .filter(|(_, output)| is_hidden_leader_output(output))
+                    .count()
+            } else if name == REPEAT {
+                macro_count(config)
+            } else {
+                0
+            }
+        })
+        .sum::<usize>()
+        + layer_hidden_keys(config);
+    // And the one that toggles the hold-back layer, which is skipped anyway
+    if needed > 0 {
+        needed + 1
+    } else {
+        0
+    }
+}
+
+/// Whether the custom key `name` holds back other keys until it is decided
+pub(crate) fn is_held_back(name: &str) -> bool {
+    name == LEADER
+        || ["TH(", "LT(", "TD(", "OSM(", "OSL("]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// Every custom key used in the layers, in the order they appear
+pub(crate) fn custom_keys(config: &toml::Table) -> Vec<CustomKey> {
+    let mut keys: Vec<CustomKey> = Vec::new();
+    // The first one toggles the hold-back layer
+    let hidden = hidden_positions(config);
+    let mut next_hidden = 1;
+    let layers = config["layer"].as_array().expect("Missing [[layer]]");
+    for layer in layers {
+        let keys_str = layer["keys"].as_str().expect("Missing keys in [[layer]]");
+        for token in tokenize_keys(keys_str) {
+            let Some(name) = custom_key_name(&token) else {
+                continue;
+            };
+            if keys.iter().any(|k| k.name == name) {
+                continue;
+            }
+            let mut next_hidden = || {
+                next_hidden += 1;
+                hidden[next_hidden - 1]
+            };
+            keys.push(if name.starts_with("TH(") || name.starts_with("LT(") {
+                tap_hold_key(&name, config, &mut next_hidden)
+            } else if name.starts_with("TD(") {
+                tap_dance_key(&name, config, &mut next_hidden)
+            } else if name.starts_with("OSM(") || name.starts_with("OSL(") {
+                one_shot_key(&name, config, &mut next_hidden)
+            } else if name == CAPS_WORD {
+                caps_word_key(config, &mut next_hidden)
+            } else if name == LEADER {
+                leader_key(config, &mut next_hidden)
+            } else if name == REPEAT {
+                repeat_key(config, &mut next_hidden)
+            } else {
+                custom_key(&name, config)
+            });
+        }
+    }
+    keys
+}
+
+/// Whether the custom key `name` is told apart by its position, rather than by
+/// a keycode of its own
+pub(crate) fn is_dispatched(name: &str) -> bool {
+    !FIXED_KEYS.iter().any(|(n, ..)| *n == name)
+}
+
+/// `User` keycode of the custom key `name`
+pub(crate) fn custom_keycode(name: &str) -> String {
+    let i = FIXED_KEYS
+        .iter()
+        .position(|(n, ..)| *n == name)
+        .unwrap_or(FIXED_KEYS.len());
+    format!("User{}", RMK_CUSTOM_KEYCODES.len() + i)
+}
+
+/// Rust code of `CUSTOM_KEYS`, `DISPATCHED_KEYCODE` and `DISPATCHED_KEYS`
+fn custom_key_tables(config: &toml::Table, keys: &[CustomKey]) -> String {
+    let entries: String = keys
+        .iter()
+        .filter(|k| !is_dispatched(&k.name))
+        .map(|k| {
+            format!(
+                "    // {}\n    (KeyCode::{}, {}),\n",
+                k.name,
+                custom_keycode(&k.name),
+                k.action
+            )
+        })
+        .collect();
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    let matrix_map = parse_matrix_map(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    let layers = config["layer"].as_array().expect("Missing [[layer]]");
+    let mut dispatched = String::new();
+    for ((layer_index, layer), layer_name) in layers.iter().enumerate().zip(layer_names(config)) {
+        let keys_str = layer["keys"].as_str().expect("Missing keys in [[layer]]");
+        for (token, &(row, col)) in tokenize_keys(keys_str).iter().zip(&matrix_map) {
+            let Some(name) = custom_key_name(token).filter(|n| is_dispatched(n)) else {
+                continue;
+            };
+            let key = keys.iter().find(|k| k.name == name).unwrap();
+            dispatched += &format!(
+                "    // {} on {}\n    DispatchedKey {{\n        layer: {},\n        pos: ({}, {}),\n        key: {},\n    }},\n",
+                name, layer_name, layer_index, row, col, key.action
+            );
+        }
+    }
+    format!(
+        "pub(crate) const CUSTOM_KEYS: [(KeyCode, CustomKey); {count}] = [
+{entries}];
+
+pub(crate) const DISPATCHED_KEYCODE: KeyCode = KeyCode::{keycode};
+
+pub(crate) const DISPATCHED_KEYS: [DispatchedKey; {dispatched_count}] = [
+{dispatched}];
+",
+        count = entries.matches("(KeyCode::").count(),
+        keycode = custom_keycode(DISPATCHED.0),
+        dispatched_count = dispatched.matches("DispatchedKey {").count(),
+    )
+}
+
+pub(crate) fn generate_custom_keys() {
+    let out_file = Path::new(&env::var_os("OUT_DIR").unwrap()).join("custom_keys_generated.rs");
+    let config = read_keyboard_toml();
+    let keys = custom_keys(&config);
+    fs::write(out_file, custom_key_tables(&config, &keys)).unwrap();
+
+    let gate = match hold_back_gate(&config, &keys) {
+        Some((row, col)) => format!("({}, {})", row, col),
+        // Never pressed without tap-hold keys
+        None => "(0, 0)".to_owned(),
+    };
+    fs::write(
+        Path::new(&env::var_os("OUT_DIR").unwrap()).join("tap_hold_generated.rs"),
+        format!(
+            "pub(crate) const HOLD_BACK_GATE: (u8, u8) = {};\npub(crate) const HOLD_BACK_LAYER: u8 = {};\n",
+            gate,
+            layer_names(&config).len()
+        ),
+    )
+    .unwrap();
+
+    fs::write(
+        Path::new(&env::var_os("OUT_DIR").unwrap()).join("leader_generated.rs"),
+        leader_tables(&config, &keys),
+    )
+    .unwrap();
+
+    fs::write(
+        Path::new(&env::var_os("OUT_DIR").unwrap()).join("repeat_generated.rs"),
+        repeat_tables(&config, &keys),
+    )
+    .unwrap();
+
+    fs::write(
+        Path::new(&env::var_os("OUT_DIR").unwrap()).join("layers_generated.rs"),
+        layer_tables(&config, &keys),
+    )
+    .unwrap();
+
+    fs::write(
+        Path::new(&env::var_os("OUT_DIR").unwrap()).join("overrides_generated.rs"),
+        override_tables(&config),
+    )
+    .unwrap();
+}
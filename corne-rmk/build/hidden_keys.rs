@@ -0,0 +1,74 @@
+//! Hidden keys, keymap positions that no switch is wired to and that the
+//! firmware sends key events for.
+
+use crate::config::parse_matrix_map;
+use crate::custom_keys::{hidden_keys_needed, is_held_back, CustomKey};
+
+/// Rows of the keymap, with extra ones beyond the matrix if its free positions
+/// aren't enough for the hidden keys
+pub(crate) fn keymap_rows(config: &toml::Table) -> usize {
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    let rows = layout["rows"].as_integer().expect("Missing layout.rows") as usize;
+    let cols = layout["cols"].as_integer().expect("Missing layout.cols") as usize;
+    let matrix_map = parse_matrix_map(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    // Without `activity_key()`
+    let free = rows * cols - matrix_map.len() - 1;
+    rows + hidden_keys_needed(config)
+        .saturating_sub(free)
+        .div_ceil(cols)
+}
+
+/// Keymap positions that aren't in `matrix_map`, in order, except `activity_key()`.
+///
+/// The keys there can't be pressed, the firmware presses them to run their actions.
+pub(crate) fn hidden_positions(config: &toml::Table) -> Vec<(usize, usize)> {
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    let rows = keymap_rows(config);
+    let cols = layout["cols"].as_integer().expect("Missing layout.cols") as usize;
+    let matrix_map = parse_matrix_map(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    let activity_key = activity_key(config);
+    (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (row, col)))
+        .filter(|pos| !matrix_map.contains(pos) && *pos != activity_key)
+        .collect()
+}
+
+/// The hidden key that does nothing, whose events tell rmk that a joystick is
+/// used, see `activity.rs`.
+///
+/// The last free position of the matrix, on the peripheral's half so that it
+/// can send it too.
+pub(crate) fn activity_key(config: &toml::Table) -> (usize, usize) {
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    let rows = layout["rows"].as_integer().expect("Missing layout.rows") as usize;
+    let cols = layout["cols"].as_integer().expect("Missing layout.cols") as usize;
+    let matrix_map = parse_matrix_map(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    (0..rows)
+        .flat_map(|row| (0..cols).map(move |col| (row, col)))
+        .rfind(|pos| !matrix_map.contains(pos))
+        .filter(|(_, col)| *col >= cols / 2)
+        .expect("The peripheral's half of the matrix needs a free position for joystick activity")
+}
+
+/// The hidden key that toggles the hold-back layer, if there are keys that hold back others
+pub(crate) fn hold_back_gate(
+    config: &toml::Table,
+    custom_keys: &[CustomKey],
+) -> Option<(usize, usize)> {
+    if !custom_keys.iter().any(|k| is_held_back(&k.name)) {
+        return None;
+    }
+    Some(hidden_positions(config)[0])
+}
@@ -0,0 +1,264 @@
+//! The default keymap, with its combos, forks and mouse key settings.
+
+use std::path::PathBuf;
+use std::{env, fs};
+
+use corne_core::host_layout::HostLayout;
+
+use crate::auto_shift::{auto_shift_action, auto_shift_rules};
+use crate::combos::combos;
+use crate::config::{
+    keycode, layer_index, layer_names, parse_matrix_map, read_keyboard_toml, split_function,
+    tokenize_keys,
+};
+use crate::custom_keys::{
+    custom_key_name, custom_keycode, custom_keys, is_dispatched, is_held_back, RMK_CUSTOM_KEYCODES,
+};
+use crate::hidden_keys::{activity_key, hold_back_gate, keymap_rows};
+use crate::layers::layer_keys;
+use crate::mouse_keys::mouse_key_config;
+use crate::overrides::overrides;
+use crate::symbols::{symbol_action, symbol_char};
+
+/// Rust expression of the `KeyAction` for a key in keyboard.toml
+pub(crate) fn key_action(token: &str, layer_names: &[String]) -> String {
+    match token {
+        "_" | "__" => return "KeyAction::Transparent".to_owned(),
+        "No" => return "k!(No)".to_owned(),
+        _ => {}
+    }
+    if let Some(i) = RMK_CUSTOM_KEYCODES.iter().position(|(n, ..)| *n == token) {
+        return format!("k!(User{})", i);
+    }
+    if let Some(name) = custom_key_name(token) {
+        // rmk turns on the hold-back layer right away, before the next key
+        if is_held_back(&name) && is_dispatched(&name) {
+            return format!("tg!({})", layer_names.len());
+        }
+        return format!("k!({})", custom_keycode(&name));
+    }
+    let Some((function, args)) = split_function(token) else {
+        return format!("k!({})", keycode(token));
+    };
+    match (function, args.as_slice()) {
+        ("LT", [layer, key]) => {
+            format!("lt!({}, {})", layer_index(layer, layer_names), keycode(key))
+        }
+        ("TH", [tap, hold]) => format!(
+            "KeyAction::TapHold(Action::Key(KeyCode::{}), Action::Key(KeyCode::{}))",
+            keycode(tap),
+            keycode(hold)
+        ),
+        ("MO", [layer]) => format!("mo!({})", layer_index(layer, layer_names)),
+        ("DF", [layer]) => format!("df!({})", layer_index(layer, layer_names)),
+        ("SHIFTED", [key]) => format!("shifted!({})", keycode(key)),
+        // The US one, `src/symbols.rs` swaps it for the host's layout
+        ("SYM", [symbol]) => symbol_action(symbol_char(symbol), HostLayout::Us),
+        _ => panic!("Unsupported key `{}` in keyboard.toml", token),
+    }
+}
+
+pub(crate) fn generate_keymap() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let out_file = out_dir.join("keymap_generated.rs");
+    let config = read_keyboard_toml();
+
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    let rows = layout["rows"].as_integer().expect("Missing layout.rows") as usize;
+    let cols = layout["cols"].as_integer().expect("Missing layout.cols") as usize;
+    let matrix_map = parse_matrix_map(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    for &(row, col) in &matrix_map {
+        if row >= rows || col >= cols {
+            panic!("({},{}) in matrix_map is outside the matrix", row, col);
+        }
+    }
+    let rows = keymap_rows(&config);
+
+    let layers = config["layer"].as_array().expect("Missing [[layer]]");
+    if let Some(num_layers) = layout.get("layers").and_then(|l| l.as_integer()) {
+        if num_layers as usize != layers.len() {
+            panic!(
+                "layout.layers is {} but keyboard.toml has {} [[layer]] blocks",
+                num_layers,
+                layers.len()
+            );
+        }
+    }
+    let layer_names = layer_names(&config);
+    let forks = overrides(&config, &layer_names);
+    let auto_shift = auto_shift_rules(&config, &layer_names);
+
+    let custom_keys = custom_keys(&config);
+    let hold_back_gate = hold_back_gate(&config, &custom_keys);
+    // Not `k!(No)`, which the firmware takes for a key of the hold-back layer
+    let activity_key = activity_key(&config);
+    // Hidden keys get their actions on the base layer and the other default layers,
+    // and are transparent above
+    let mut hidden_actions: Vec<((usize, usize), String)> = custom_keys
+        .iter()
+        .flat_map(|k| k.hidden_actions.iter().cloned())
+        .collect();
+    if let Some(gate) = hold_back_gate {
+        hidden_actions.push((gate, format!("tg!({})", layers.len())));
+    }
+    let (rule_keys, default_keys) = layer_keys(&config, &custom_keys);
+    for (layer, pos) in rule_keys {
+        hidden_actions.push((pos, format!("mo!({})", layer)));
+    }
+    for (layer, pos) in &default_keys {
+        hidden_actions.push((*pos, format!("df!({})", layer)));
+    }
+    let mut keymap = String::new();
+    // The actions of each layer, for the combos
+    let mut layer_actions = Vec::new();
+    let mut symbols = String::new();
+    for (layer_index, (layer, name)) in layers.iter().zip(&layer_names).enumerate() {
+        let keys = layer["keys"].as_str().expect("Missing keys in [[layer]]");
+        let tokens = tokenize_keys(keys);
+        if tokens.len() != matrix_map.len() {
+            panic!(
+                "Layer {} has {} keys, but matrix_map has {}",
+                name,
+                tokens.len(),
+                matrix_map.len()
+            );
+        }
+        let mut actions = vec![vec!["k!(No)".to_owned(); cols]; rows];
+        actions[activity_key.0][activity_key.1] = "KeyAction::No".to_owned();
+        for ((row, col), action) in &hidden_actions {
+            // Layers below the default one are off
+            actions[*row][*col] = if layer_index == 0
+                || default_keys.iter().any(|(layer, _)| *layer == layer_index)
+            {
+                action.clone()
+            } else {
+                "KeyAction::Transparent".to_owned()
+            };
+        }
+        for (token, &(row, col)) in tokens.iter().zip(&matrix_map) {
+            actions[row][col] = key_action(token, &layer_names);
+            if let Some(action) = auto_shift_action(&auto_shift, &actions[row][col], layer_index) {
+                actions[row][col] = action;
+            }
+            if let Some(("SYM", args)) = split_function(token) {
+                let c = symbol_char(args[0]);
+                let actions: Vec<String> = HostLayout::ALL
+                    .iter()
+                    .map(|&layout| symbol_action(c, layout))
+                    .collect();
+                symbols += &format!(
+                    "    // {} on {}\n    SymbolKey {{\n        layer: {},\n        row: {},\n        col: {},\n        actions: [{}],\n    }},\n",
+                    c,
+                    name,
+                    layer_index,
+                    row,
+                    col,
+                    actions.join(", ")
+                );
+            }
+        }
+        keymap += &format!("        // {}\n        [\n", name);
+        for row in &actions {
+            keymap += &format!("            [{}],\n", row.join(", "));
+        }
+        keymap += "        ],\n";
+        layer_actions.push(actions);
+    }
+    if hold_back_gate.is_some() {
+        // Every key of the matrix does nothing, the firmware tells them apart by position
+        let mut actions = vec![vec!["k!(No)".to_owned(); cols]; rows];
+        actions[activity_key.0][activity_key.1] = "KeyAction::No".to_owned();
+        for ((row, col), _) in &hidden_actions {
+            actions[*row][*col] = "KeyAction::Transparent".to_owned();
+        }
+        keymap += "        // Hold-back layer, on while a key is undecided or a Leader sequence is typed\n        [\n";
+        for row in actions {
+            keymap += &format!("            [{}],\n", row.join(", "));
+        }
+        keymap += "        ],\n";
+    }
+
+    // Import only the macros in use, to keep the generated code free of warnings
+    let used_macros = |code: &str| {
+        ["df", "k", "lt", "mo", "shifted", "tg", "wm"]
+            .into_iter()
+            .filter(|m| code.contains(&format!("{}!(", m)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let (combo_timeout_ms, combos) = combos(&config, &layer_names, &layer_actions);
+    let code = keymap.clone() + &combos + &forks;
+    let mut imports = format!("use rmk::{{{}}};\n", used_macros(&code));
+    if code.contains("ModifierCombination") {
+        imports += "use rmk::keycode::ModifierCombination;\n";
+    }
+    if code.contains("KeyAction::TapHold") {
+        imports += "use rmk::action::Action;\nuse rmk::keycode::KeyCode;\n";
+    }
+    if !combos.is_empty() {
+        imports += "use rmk::combo::Combo;\n";
+    }
+    if !forks.is_empty() {
+        imports += "use rmk::fork::{Fork, StateBits};\nuse rmk::hid_state::{HidModifiers, HidMouseButtons};\nuse rmk::light::LedIndicator;\n";
+    }
+    let generated = format!(
+        "{imports}use embassy_time::Duration;
+use rmk::config::{{CombosConfig, ForksConfig, MouseKeyConfig}};
+use rmk::heapless::Vec;
+
+pub(crate) const ROW: usize = {rows};
+pub(crate) const COL: usize = {cols};
+pub(crate) const NUM_LAYER: usize = {num_layer};
+
+/// The hidden key whose events tell rmk that a joystick is used
+pub(crate) const ACTIVITY_KEY: (u8, u8) = {activity_key:?};
+
+pub const fn get_default_keymap() -> [[[KeyAction; COL]; ROW]; NUM_LAYER] {{
+    [
+{keymap}    ]
+}}
+
+pub(crate) fn get_combos() -> CombosConfig {{
+    CombosConfig {{
+        combos: Vec::from_iter([
+{combos}        ]),
+        timeout: Duration::from_millis({combo_timeout_ms}),
+    }}
+}}
+
+pub(crate) fn get_forks() -> ForksConfig {{
+    ForksConfig {{
+        forks: Vec::from_iter([
+{forks}        ]),
+    }}
+}}
+
+pub(crate) fn get_mouse_key_config() -> MouseKeyConfig {{
+    {mouse_key_config}
+}}
+",
+        mouse_key_config = mouse_key_config(&config),
+        num_layer = layers.len() + hold_back_gate.is_some() as usize,
+    );
+    fs::write(out_file, generated).unwrap();
+
+    let mut imports = String::new();
+    if !symbols.is_empty() {
+        imports += &format!("use rmk::{{{}}};\n", used_macros(&symbols));
+    }
+    if symbols.contains("ModifierCombination") {
+        imports += "use rmk::keycode::ModifierCombination;\n";
+    }
+    let generated = format!(
+        "{imports}
+pub(crate) const SYMBOL_KEYS: [SymbolKey; {count}] = [
+{symbols}];
+",
+        count = symbols.matches("SymbolKey {").count(),
+    );
+    fs::write(out_dir.join("symbols_generated.rs"), generated).unwrap();
+}
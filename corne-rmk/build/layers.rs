@@ -0,0 +1,157 @@
+//! `[[conditional_layer]]` blocks and the layers that can be the default one.
+
+use crate::config::{layer_index, layer_names, split_function, tokenize_keys};
+use crate::custom_keys::CustomKey;
+use crate::hidden_keys::hidden_positions;
+
+/// The `[[conditional_layer]]` blocks, as the layers that turn on another one
+/// and that layer
+pub(crate) fn conditional_layers(
+    config: &toml::Table,
+    layer_names: &[String],
+) -> Vec<(Vec<usize>, usize)> {
+    let rules: Vec<(Vec<usize>, usize)> = config
+        .get("conditional_layer")
+        .map(|c| {
+            c.as_array()
+                .expect("[[conditional_layer]] must be an array")
+                .clone()
+        })
+        .unwrap_or_default()
+        .iter()
+        .map(|rule| {
+            let rule = rule
+                .as_table()
+                .expect("[[conditional_layer]] must be a table");
+            if let Some(key) = rule.keys().find(|k| *k != "layers" && *k != "then") {
+                panic!("Unknown setting `{}` in [[conditional_layer]]", key);
+            }
+            let layers: Vec<usize> = rule
+                .get("layers")
+                .and_then(|l| l.as_array())
+                .expect("Missing layers in [[conditional_layer]]")
+                .iter()
+                .map(|l| {
+                    layer_index(
+                        l.as_str().expect("Conditional layers are strings"),
+                        layer_names,
+                    )
+                })
+                .collect();
+            let then = layer_index(
+                rule.get("then")
+                    .and_then(|t| t.as_str())
+                    .expect("Missing then in [[conditional_layer]]"),
+                layer_names,
+            );
+            if layers.is_empty() || layers.contains(&then) {
+                panic!(
+                    "Conditional layer {} needs other layers to turn it on",
+                    layer_names[then]
+                );
+            }
+            (layers, then)
+        })
+        .collect();
+    // Bit masks of `u32` in the firmware
+    if !rules.is_empty() && layer_names.len() > 32 {
+        panic!("Conditional layers only work with up to 32 layers");
+    }
+    rules
+}
+
+/// Layers that `DF(..)` keys select as the default one, besides the base layer
+pub(crate) fn default_layers(config: &toml::Table, layer_names: &[String]) -> Vec<usize> {
+    let mut layers = Vec::new();
+    for layer in config["layer"].as_array().expect("Missing [[layer]]") {
+        let keys_str = layer["keys"].as_str().expect("Missing keys in [[layer]]");
+        for token in tokenize_keys(keys_str) {
+            if let Some(("DF", [layer])) = split_function(&token)
+                .as_ref()
+                .map(|(f, args)| (*f, args.as_slice()))
+            {
+                let layer = layer_index(layer, layer_names);
+                if layer != 0 && !layers.contains(&layer) {
+                    layers.push(layer);
+                }
+            }
+        }
+    }
+    layers
+}
+
+/// Hidden keys for the layers that conditional layers turn on and for the
+/// default layers, see `layer_keys()`
+pub(crate) fn layer_hidden_keys(config: &toml::Table) -> usize {
+    let layer_names = layer_names(config);
+    let mut then: Vec<usize> = conditional_layers(config, &layer_names)
+        .into_iter()
+        .map(|(_, then)| then)
+        .collect();
+    then.sort();
+    then.dedup();
+    then.len() + default_layers(config, &layer_names).len()
+}
+
+/// The hidden keys that hold on each layer that conditional layers turn on, and
+/// those that select each default layer, as `(layer, position)`.
+///
+/// They come after those of the custom keys.
+#[allow(clippy::type_complexity)]
+pub(crate) fn layer_keys(
+    config: &toml::Table,
+    custom_keys: &[CustomKey],
+) -> (Vec<(usize, (usize, usize))>, Vec<(usize, (usize, usize))>) {
+    let layer_names = layer_names(config);
+    let used: usize = custom_keys.iter().map(|k| k.hidden_actions.len()).sum();
+    // After the one that toggles the hold-back layer
+    let mut hidden = hidden_positions(config).into_iter().skip(1 + used);
+    let mut rule_keys: Vec<(usize, (usize, usize))> = Vec::new();
+    for (_, then) in conditional_layers(config, &layer_names) {
+        if !rule_keys.iter().any(|(layer, _)| *layer == then) {
+            rule_keys.push((then, hidden.next().unwrap()));
+        }
+    }
+    let default_keys = default_layers(config, &layer_names)
+        .into_iter()
+        .map(|layer| (layer, hidden.next().unwrap()))
+        .collect();
+    (rule_keys, default_keys)
+}
+
+/// Rust code of the conditional layers and the hidden keys of `layer_keys()`
+pub(crate) fn layer_tables(config: &toml::Table, custom_keys: &[CustomKey]) -> String {
+    let layer_names = layer_names(config);
+    let rules: String = conditional_layers(config, &layer_names)
+        .iter()
+        .map(|(layers, then)| {
+            let names: Vec<&str> = layers.iter().map(|&l| layer_names[l].as_str()).collect();
+            let mask = layers.iter().fold(0u32, |mask, l| mask | 1 << l);
+            format!(
+                "    // {} turn on {}\n    Rule {{ when: {:#x}, then: {} }},\n",
+                names.join(" and "),
+                layer_names[*then],
+                mask,
+                then
+            )
+        })
+        .collect();
+    let (rule_keys, default_keys) = layer_keys(config, custom_keys);
+    let keys = |keys: &[(usize, (usize, usize))]| {
+        keys.iter()
+            .map(|(layer, (row, col))| format!("({}, ({}, {}))", layer, row, col))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "pub(crate) static LAYER_RULES: [Rule; {}] = [\n{}];\n\n\
+         pub(crate) const RULE_KEYS: [(u8, (u8, u8)); {}] = [{}];\n\n\
+         pub(crate) const DEFAULT_LAYER_KEYS: [(u8, (u8, u8)); {}] = [{}];\n",
+        conditional_layers(config, &layer_names).len(),
+        rules,
+        rule_keys.len(),
+        keys(&rule_keys),
+        default_keys.len(),
+        keys(&default_keys)
+    )
+}
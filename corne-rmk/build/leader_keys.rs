@@ -0,0 +1,195 @@
+//! The `[leader]` block, the Leader key and its sequences.
+
+use corne_core::leader;
+
+use crate::config::{
+    layer_names, parse_matrix_map, section_timeout_ms, tokenize_keys, unicode_char,
+};
+use crate::custom_keys::{custom_key_name, CustomKey};
+use crate::keymap::key_action;
+
+/// Starts a Leader key sequence, see `src/leader_keys.rs`
+pub(crate) const LEADER: &str = "LEADER";
+
+/// The `[[leader.sequence]]` blocks, as `(keys, output)` where the output is
+/// a string to type or a key
+pub(crate) fn leader_sequences(config: &toml::Table) -> Vec<(Vec<&str>, LeaderOutput<'_>)> {
+    let Some(leader) = config.get("leader").and_then(|l| l.as_table()) else {
+        return Vec::new();
+    };
+    leader
+        .get("sequence")
+        .and_then(|s| s.as_array())
+        .map_or(&[][..], |s| s.as_slice())
+        .iter()
+        .map(|sequence| {
+            let keys: Vec<&str> = sequence
+                .get("keys")
+                .and_then(|k| k.as_array())
+                .expect("Missing keys in [[leader.sequence]]")
+                .iter()
+                .map(|k| k.as_str().expect("Leader sequence keys are strings"))
+                .collect();
+            let output = match (
+                sequence.get("text").and_then(|t| t.as_str()),
+                sequence.get("output").and_then(|o| o.as_str()),
+            ) {
+                (Some(text), None) => LeaderOutput::Text(text),
+                (None, Some(output)) => LeaderOutput::Key(output),
+                _ => panic!(
+                    "[[leader.sequence]] {} needs either text or output",
+                    keys.join(" ")
+                ),
+            };
+            (keys, output)
+        })
+        .collect()
+}
+
+/// What a Leader key sequence does
+pub(crate) enum LeaderOutput<'a> {
+    Text(&'a str),
+    /// `UC(..)` or one of rmk's keys, which goes on a hidden key
+    Key(&'a str),
+}
+
+/// Whether `output` of a Leader key sequence goes on a hidden key
+pub(crate) fn is_hidden_leader_output(output: &LeaderOutput) -> bool {
+    match output {
+        LeaderOutput::Text(_) => false,
+        LeaderOutput::Key(token) => match custom_key_name(token) {
+            Some(name) if name.starts_with("UC(") => false,
+            Some(_) => panic!(
+                "Leader sequences can only type text, UC(..) or one of rmk's keys, not `{}`",
+                token
+            ),
+            None => true,
+        },
+    }
+}
+
+/// The custom key of `LEADER`, the outputs of its sequences that are rmk's keys
+/// go on hidden keys from `next_hidden`
+pub(crate) fn leader_key(
+    config: &toml::Table,
+    next_hidden: &mut dyn FnMut() -> (usize, usize),
+) -> CustomKey {
+    let layer_names = layer_names(config);
+    let hidden_actions = leader_sequences(config)
+        .iter()
+        .filter(|(_, output)| is_hidden_leader_output(output))
+        .map(|(_, output)| match output {
+            LeaderOutput::Key(token) => (next_hidden(), key_action(token, &layer_names)),
+            LeaderOutput::Text(_) => unreachable!(),
+        })
+        .collect();
+    CustomKey {
+        name: LEADER.to_owned(),
+        action: "CustomKey::Leader".to_owned(),
+        hidden_actions,
+    }
+}
+
+/// Rust code of the Leader key sequences: the timeout, the trie of the key
+/// positions on the base layer and the action of each sequence
+pub(crate) fn leader_tables(config: &toml::Table, custom_keys: &[CustomKey]) -> String {
+    let sequences = leader_sequences(config);
+    let timeout = section_timeout_ms(config, "leader", &["sequence"], "1s");
+    let layer_names = layer_names(config);
+    // What a key is, to find the keys of the sequences on the base layer
+    let identity =
+        |token: &str| custom_key_name(token).unwrap_or_else(|| key_action(token, &layer_names));
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    let matrix_map = parse_matrix_map(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    let base_layer = config["layer"].as_array().expect("Missing [[layer]]")[0]["keys"]
+        .as_str()
+        .expect("Missing keys in [[layer]]");
+    let base_keys: Vec<String> = tokenize_keys(base_layer)
+        .iter()
+        .map(|token| identity(token))
+        .collect();
+    let positions: Vec<Vec<u16>> = sequences
+        .iter()
+        .map(|(keys, _)| {
+            keys.iter()
+                .map(|key| {
+                    let i = base_keys
+                        .iter()
+                        .position(|k| *k == identity(key))
+                        .unwrap_or_else(|| {
+                            panic!("Leader sequence key `{}` isn't on the base layer", key)
+                        });
+                    let (row, col) = matrix_map[i];
+                    ((row as u16) << 8) | col as u16
+                })
+                .collect()
+        })
+        .collect();
+    let slices: Vec<&[u16]> = positions.iter().map(Vec::as_slice).collect();
+    let mut nodes = vec![leader::Node::EMPTY; 1 + positions.iter().map(Vec::len).sum::<usize>()];
+    let used = leader::build(&slices, &mut nodes).unwrap_or_else(|e| match e {
+        leader::BuildError::Empty(i) => panic!("[[leader.sequence]] number {} has no keys", i + 1),
+        leader::BuildError::Duplicate(i) => panic!(
+            "[[leader.sequence]] {} has the keys of an earlier one",
+            sequences[i].0.join(" ")
+        ),
+        leader::BuildError::TooManyNodes => unreachable!(),
+    });
+
+    let mut hidden = custom_keys
+        .iter()
+        .find(|k| k.name == LEADER)
+        .map_or(&[][..], |k| k.hidden_actions.as_slice())
+        .iter()
+        .map(|(pos, _)| *pos);
+    let actions: String = sequences
+        .iter()
+        .map(|(keys, output)| {
+            let action = match output {
+                LeaderOutput::Text(text) => format!("LeaderAction::Text({:?})", text),
+                output if is_hidden_leader_output(output) => {
+                    format!("LeaderAction::Key({:?})", hidden.next().unwrap())
+                }
+                LeaderOutput::Key(token) => format!(
+                    "LeaderAction::Unicode('\\u{{{:x}}}')",
+                    unicode_char(&token["UC(".len()..token.len() - 1]) as u32
+                ),
+            };
+            format!("    // {}\n    {},\n", keys.join(" "), action)
+        })
+        .collect();
+    let value = |v: u16| match v {
+        leader::NONE => "NONE".to_owned(),
+        v => v.to_string(),
+    };
+    let nodes: String = nodes[..used]
+        .iter()
+        .map(|n| {
+            format!(
+                "    Node {{ key: {}, first_child: {}, next_sibling: {}, action: {} }},\n",
+                match n.key {
+                    leader::NONE => "NONE".to_owned(),
+                    key => format!("{:#06x}", key),
+                },
+                value(n.first_child),
+                value(n.next_sibling),
+                value(n.action)
+            )
+        })
+        .collect();
+    format!(
+        "pub(crate) const LEADER_TIMEOUT_MS: u64 = {};\n\n\
+         /// Keys are positions on the base layer, as `row << 8 | col`\n\
+         pub(crate) static LEADER_NODES: [Node; {}] = [\n{}];\n\n\
+         pub(crate) static LEADER_ACTIONS: [LeaderAction; {}] = [\n{}];\n",
+        timeout,
+        used,
+        nodes,
+        sequences.len(),
+        actions
+    )
+}
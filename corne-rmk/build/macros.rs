@@ -0,0 +1,161 @@
+//! `[[macro]]` blocks.
+
+use std::path::Path;
+use std::{env, fs};
+
+use crate::config::{key_alias, keycode, read_keyboard_toml};
+
+/// Number of `[[macro]]` blocks
+pub(crate) fn macro_count(config: &toml::Table) -> usize {
+    config
+        .get("macro")
+        .and_then(|m| m.as_array())
+        .map_or(0, |m| m.len())
+}
+
+/// Key and whether it needs Shift, to type `c` on a US host layout
+pub(crate) fn us_key_for_char(c: char) -> Option<(String, bool)> {
+    let unshifted = |k: &str| Some((k.to_owned(), false));
+    let shifted = |k: &str| Some((k.to_owned(), true));
+    match c {
+        'a'..='z' => unshifted(&c.to_ascii_uppercase().to_string()),
+        'A'..='Z' => shifted(&c.to_string()),
+        '0'..='9' => unshifted(&format!("Kc{}", c)),
+        ' ' => unshifted("Space"),
+        '\n' => unshifted("Enter"),
+        '\t' => unshifted("Tab"),
+        '!' => shifted("Kc1"),
+        '@' => shifted("Kc2"),
+        '#' => shifted("Kc3"),
+        '$' => shifted("Kc4"),
+        '%' => shifted("Kc5"),
+        '^' => shifted("Kc6"),
+        '&' => shifted("Kc7"),
+        '*' => shifted("Kc8"),
+        '(' => shifted("Kc9"),
+        ')' => shifted("Kc0"),
+        '_' => shifted("Minus"),
+        '+' => shifted("Equal"),
+        '{' => shifted("LeftBracket"),
+        '}' => shifted("RightBracket"),
+        '|' => shifted("Backslash"),
+        ':' => shifted("Semicolon"),
+        '"' => shifted("Quote"),
+        '~' => shifted("Grave"),
+        '<' => shifted("Comma"),
+        '>' => shifted("Dot"),
+        '?' => shifted("Slash"),
+        _ => key_alias(&c.to_string()).and_then(unshifted),
+    }
+}
+
+/// `MacroOperation`s of one operation in a `[[macro]]` block, with their encoded size
+pub(crate) fn macro_operations(operation: &toml::Value, name: &str) -> Vec<(String, usize)> {
+    // rmk encodes key operations as 3 bytes and delays as 4, like Vial does
+    let key_op = |op: &str, key: &str| (format!("MacroOperation::{}(KeyCode::{})", op, key), 3);
+    let table = operation
+        .as_table()
+        .filter(|t| t.len() == 1)
+        .unwrap_or_else(|| panic!("Macro {} has an invalid operation {}", name, operation));
+    let (kind, value) = table.iter().next().unwrap();
+    let key = || {
+        keycode(
+            value
+                .as_str()
+                .unwrap_or_else(|| panic!("Macro {}: {} needs a key", name, kind)),
+        )
+    };
+    match kind.as_str() {
+        "press" => vec![key_op("Press", &key())],
+        "release" => vec![key_op("Release", &key())],
+        "tap" => vec![key_op("Tap", &key())],
+        "delay" => {
+            let ms = value
+                .as_integer()
+                .filter(|ms| (0..=u16::MAX as i64).contains(ms))
+                .unwrap_or_else(|| panic!("Macro {}: delay needs milliseconds", name));
+            vec![(format!("MacroOperation::Delay({})", ms), 4)]
+        }
+        "text" => value
+            .as_str()
+            .unwrap_or_else(|| panic!("Macro {}: text needs a string", name))
+            .chars()
+            .flat_map(|c| {
+                let (key, shift) = us_key_for_char(c)
+                    .unwrap_or_else(|| panic!("Macro {}: cannot type {:?}", name, c));
+                if shift {
+                    vec![
+                        key_op("Press", "LShift"),
+                        key_op("Tap", &key),
+                        key_op("Release", "LShift"),
+                    ]
+                } else {
+                    vec![key_op("Tap", &key)]
+                }
+            })
+            .collect(),
+        _ => panic!("Macro {} has an unknown operation `{}`", name, kind),
+    }
+}
+
+pub(crate) fn generate_macros() {
+    let out_file = Path::new(&env::var_os("OUT_DIR").unwrap()).join("macros_generated.rs");
+    let config = read_keyboard_toml();
+    let macros = config
+        .get("macro")
+        .map(|m| m.as_array().expect("[[macro]] must be an array").clone())
+        .unwrap_or_default();
+
+    let generated = if macros.is_empty() {
+        "pub(crate) fn get_macro_sequences() -> [u8; MACRO_SPACE_SIZE] {
+    [0; MACRO_SPACE_SIZE]
+}
+"
+        .to_owned()
+    } else {
+        let mut sequences = String::new();
+        // Every macro is terminated by one byte
+        let mut size = 0;
+        for (i, m) in macros.iter().enumerate() {
+            let name = m
+                .get("name")
+                .and_then(|n| n.as_str())
+                .map(str::to_owned)
+                .unwrap_or_else(|| format!("Macro{}", i));
+            let operations = m
+                .get("operations")
+                .and_then(|o| o.as_array())
+                .unwrap_or_else(|| panic!("Macro {} has no operations", name));
+            let operations: Vec<(String, usize)> = operations
+                .iter()
+                .flat_map(|op| macro_operations(op, &name))
+                .collect();
+            size += operations.iter().map(|(_, s)| s).sum::<usize>() + 1;
+            let operations: Vec<String> = operations.into_iter().map(|(op, _)| op).collect();
+            sequences += &format!(
+                "        // {}\n        Vec::from_slice(&[\n            {},\n        ])\n        .expect(\"too many elements\"),\n",
+                name,
+                operations.join(",\n            ")
+            );
+        }
+        format!(
+            "use rmk::heapless::Vec;
+use rmk::keyboard_macros::{{define_macro_sequences, MacroOperation}};
+use rmk::keycode::KeyCode;
+
+/// Encoded size of the macros in keyboard.toml
+pub(crate) const MACRO_SEQUENCES_SIZE: usize = {size};
+pub(crate) const _: () = assert!(
+    MACRO_SEQUENCES_SIZE <= MACRO_SPACE_SIZE,
+    \"The macros in keyboard.toml don't fit into MACRO_SPACE_SIZE\"
+);
+
+pub(crate) fn get_macro_sequences() -> [u8; MACRO_SPACE_SIZE] {{
+    define_macro_sequences(&[
+{sequences}    ])
+}}
+"
+        )
+    };
+    fs::write(out_file, generated).unwrap();
+}
@@ -9,30 +9,69 @@
 //! new memory settings.
 //!
 //! The build script also sets the linker flags to tell it which link script to use.
+//!
+//! The default keymap, combos, custom keys, symbol keys, tap-hold, tap-dance and one-shot keys,
+//! Leader key sequences, Alt-Repeat pairs, auto-shift, conditional layers, mouse key settings
+//! and macros are generated from the `[[layer]]`, `[combos]`, `[tap_hold]`, `[tap_dance]`,
+//! `[one_shot]`, `[caps_word]`, `[leader]`, `[repeat]`, `[[auto_shift]]`,
+//! `[[conditional_layer]]`, `[mouse_keys]` and `[[macro]]` blocks in `keyboard.toml`,
+//! and the pins used by `central.rs` and `peripheral.rs` are checked against it.
+//! The storage regions and the settings sector of `[memory]` are checked against the FLASH region
+//! in `memory.x`, and the linker checks that the final image stays clear of them.
+
+mod auto_shift;
+mod combos;
+mod config;
+mod custom_keys;
+mod hidden_keys;
+mod keymap;
+mod layers;
+mod leader_keys;
+mod macros;
+mod memory;
+mod mouse_keys;
+mod overrides;
+mod pins;
+mod repeat_keys;
+mod symbols;
+mod tap_hold_keys;
+mod vial;
 
-use const_gen::*;
+use std::env;
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
-use std::{env, fs};
-use xz2::read::XzEncoder;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::custom_keys::generate_custom_keys;
+use crate::keymap::generate_keymap;
+use crate::macros::generate_macros;
+use crate::memory::check_memory_map;
+use crate::pins::check_pins;
+use crate::vial::generate_vial_config;
 
 fn main() {
-    // Generate vial config at the root of project
-    println!("cargo:rerun-if-changed=vial.json");
+    // Generate vial config and keymap from keyboard.toml
     println!("cargo:rerun-if-changed=keyboard.toml");
 
     generate_vial_config();
+    generate_keymap();
+    generate_custom_keys();
+    generate_macros();
+    println!("cargo:rerun-if-changed=src/central.rs");
+    println!("cargo:rerun-if-changed=src/peripheral.rs");
+    check_pins();
 
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
     File::create(out.join("memory.x"))
         .unwrap()
-        .write_all(include_bytes!("memory.x"))
+        .write_all(include_bytes!("../memory.x"))
         .unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
+    check_memory_map(out);
+
     // By default, Cargo will re-run a build script whenever
     // any file in the project changes. By specifying `memory.x`
     // here, we ensure the build script is only re-run when
@@ -55,33 +94,3 @@ fn main() {
     // Use flip-link overflow check: https://github.com/knurling-rs/flip-link
     println!("cargo:rustc-linker=flip-link");
 }
-
-fn generate_vial_config() {
-    // Generated vial config file
-    let out_file = Path::new(&env::var_os("OUT_DIR").unwrap()).join("config_generated.rs");
-
-    let p = Path::new("vial.json");
-    let mut content = String::new();
-    match File::open(p) {
-        Ok(mut file) => {
-            file.read_to_string(&mut content)
-                .expect("Cannot read vial.json");
-        }
-        Err(e) => println!("Cannot find vial.json {:?}: {}", p, e),
-    };
-
-    let vial_cfg = json::stringify(json::parse(&content).unwrap());
-    let mut keyboard_def_compressed: Vec<u8> = Vec::new();
-    XzEncoder::new(vial_cfg.as_bytes(), 6)
-        .read_to_end(&mut keyboard_def_compressed)
-        .unwrap();
-
-    let keyboard_id: Vec<u8> = vec![0xB9, 0xBC, 0x09, 0xB2, 0x9D, 0x37, 0x4C, 0xEA];
-    let const_declarations = [
-        const_declaration!(pub VIAL_KEYBOARD_DEF = keyboard_def_compressed),
-        const_declaration!(pub VIAL_KEYBOARD_ID = keyboard_id),
-    ]
-    .map(|s| "#[allow(clippy::redundant_static_lifetimes)]\n".to_owned() + s.as_str())
-    .join("\n");
-    fs::write(out_file, const_declarations).unwrap();
-}
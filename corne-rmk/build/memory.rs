@@ -0,0 +1,199 @@
+//! The flash addresses of `[memory]` in keyboard.toml, checked against `memory.x`.
+
+use std::fs;
+use std::path::Path;
+
+use crate::config::read_keyboard_toml;
+
+/// Flash sector size of the nRF52840
+pub(crate) const FLASH_SECTOR_SIZE: u32 = 0x1000;
+
+/// The Adafruit nRF52 bootloader starts from 0xF4000(976K)
+pub(crate) const BOOTLOADER_START: u32 = 0xF4000;
+
+/// Parse `0xA0000`, `4096` or `1020K`
+pub(crate) fn parse_size(value: &str) -> u32 {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(k) = value.strip_suffix('K') {
+        k.parse().map(|k: u32| k * 1024)
+    } else if let Some(m) = value.strip_suffix('M') {
+        m.parse().map(|m: u32| m * 1024 * 1024)
+    } else {
+        value.parse()
+    }
+    .unwrap_or_else(|_| panic!("Invalid size `{}`", value))
+}
+
+/// Value of `key: value` or `key = value` in `source`, up to the next `,`
+pub(crate) fn field_value<'a>(source: &'a str, key: &str, file: &str) -> &'a str {
+    let start = source
+        .find(key)
+        .unwrap_or_else(|| panic!("Cannot find `{}` in {}", key, file))
+        + key.len();
+    let value = source[start..].trim_start_matches([' ', ':', '=']);
+    value[..value.find([',', '\n']).unwrap_or(value.len())].trim()
+}
+
+/// `[start, end)` of the FLASH region in `memory.x`
+pub(crate) fn memory_x_flash() -> (u32, u32) {
+    let memory_x = fs::read_to_string("memory.x").expect("Cannot read memory.x");
+    // Drop `/* */` comments, memory.x keeps the layout without bootloader in them
+    let mut source = String::new();
+    let mut rest = memory_x.as_str();
+    while let Some(start) = rest.find("/*") {
+        source += &rest[..start];
+        rest = &rest[start..];
+        rest = &rest[rest.find("*/").expect("Unterminated comment in memory.x") + 2..];
+    }
+    source += rest;
+
+    let flash = &source[source
+        .find("FLASH")
+        .expect("Missing FLASH region in memory.x")..];
+    let origin = parse_size(field_value(flash, "ORIGIN", "memory.x"));
+    let length = parse_size(field_value(flash, "LENGTH", "memory.x"));
+    (origin, origin + length)
+}
+
+/// `[memory]` of keyboard.toml, the flash addresses of both halves
+struct Memory {
+    /// Start and number of sectors of rmk's storage, of the central and the peripheral
+    storage: [(u32, u32); 2],
+    settings_addr: u32,
+}
+
+fn parse_memory(config: &toml::Table) -> Memory {
+    let memory = config
+        .get("memory")
+        .and_then(|m| m.as_table())
+        .expect("Missing [memory]");
+    let number = |table: &toml::Table, key: &str, section: &str| -> u32 {
+        table
+            .get(key)
+            .and_then(|v| v.as_integer())
+            .and_then(|v| u32::try_from(v).ok())
+            .unwrap_or_else(|| panic!("Missing {} in {}", key, section))
+    };
+    let storage = ["central_storage", "peripheral_storage"].map(|key| {
+        let section = format!("memory.{}", key);
+        let table = memory
+            .get(key)
+            .and_then(|s| s.as_table())
+            .unwrap_or_else(|| panic!("Missing {}", section));
+        let num_sectors = number(table, "num_sectors", &section);
+        if num_sectors == 0 || num_sectors > u8::MAX as u32 {
+            panic!("{}.num_sectors has to be 1 to 255", section);
+        }
+        (number(table, "start_addr", &section), num_sectors)
+    });
+    Memory {
+        storage,
+        settings_addr: number(memory, "settings_addr", "[memory]"),
+    }
+}
+
+/// Rust code of the flash addresses of both halves, see `src/memory.rs`
+fn memory_constants(config: &toml::Table, memory: &Memory) -> String {
+    let clear_storage = config
+        .get("storage")
+        .and_then(|s| s.get("clear_storage"))
+        .and_then(|c| c.as_bool())
+        .unwrap_or(false);
+    let [(central_start, central_sectors), (peripheral_start, peripheral_sectors)] = memory.storage;
+    format!(
+        "pub(crate) const CENTRAL_STORAGE_START: usize = {:#X};\n\
+         pub(crate) const CENTRAL_STORAGE_SECTORS: u8 = {};\n\
+         pub(crate) const PERIPHERAL_STORAGE_START: usize = {:#X};\n\
+         pub(crate) const PERIPHERAL_STORAGE_SECTORS: u8 = {};\n\
+         pub(crate) const CLEAR_STORAGE: bool = {};\n\
+         pub(crate) const SETTINGS_ADDR: u32 = {:#X};\n",
+        central_start,
+        central_sectors,
+        peripheral_start,
+        peripheral_sectors,
+        clear_storage,
+        memory.settings_addr
+    )
+}
+
+/// The storage of both binaries and the settings must be flash sectors inside
+/// FLASH in memory.x, before the bootloader and apart from each other. Both
+/// binaries take their addresses from `[memory]` in keyboard.toml.
+///
+/// The size of the image is only known after linking, so a linker script with
+/// assertions is added to each binary, that the image ends before its storage
+/// and that the storage ends inside FLASH.
+pub(crate) fn check_memory_map(out: &Path) {
+    let config = read_keyboard_toml();
+    let memory = parse_memory(&config);
+    fs::write(
+        out.join("memory_generated.rs"),
+        memory_constants(&config, &memory),
+    )
+    .unwrap();
+
+    let (flash_start, flash_end) = memory_x_flash();
+    let (settings_start, settings_end) = (
+        memory.settings_addr,
+        memory.settings_addr + FLASH_SECTOR_SIZE,
+    );
+    let mut regions = vec![("settings", "central", settings_start, settings_end)];
+    for (bin, (start, num_sectors)) in ["central", "peripheral"].into_iter().zip(memory.storage) {
+        regions.push((
+            "storage",
+            bin,
+            start,
+            start + num_sectors * FLASH_SECTOR_SIZE,
+        ));
+    }
+    for &(what, bin, start, end) in &regions {
+        if start % FLASH_SECTOR_SIZE != 0 {
+            panic!(
+                "The {} of {} at {:#X} is not aligned to a flash sector",
+                what, bin, start
+            );
+        }
+        if start < flash_start || end > flash_end {
+            panic!(
+                "The {} of {} at {:#X}..{:#X} is outside FLASH {:#X}..{:#X} in memory.x",
+                what, bin, start, end, flash_start, flash_end
+            );
+        }
+        if end > BOOTLOADER_START {
+            panic!(
+                "The {} of {} at {:#X}..{:#X} overlaps the bootloader at {:#X}",
+                what, bin, start, end, BOOTLOADER_START
+            );
+        }
+    }
+    // Both halves run the same bootloader, but only the central has settings
+    let (_, _, storage_start, storage_end) = regions[1];
+    if storage_start < settings_end && settings_start < storage_end {
+        panic!(
+            "The storage of central at {:#X}..{:#X} overlaps the settings at {:#X}",
+            storage_start, storage_end, settings_start
+        );
+    }
+
+    for &(_, bin, start, end) in &regions[1..] {
+        // The image of the central must also end before its settings
+        let (image_end, data) = if bin == "central" {
+            (start.min(settings_start), "storage or settings")
+        } else {
+            (start, "storage")
+        };
+        let script = format!("storage_{}.x", bin);
+        fs::write(
+            out.join(&script),
+            format!(
+                "ASSERT(LOADADDR(.data) + SIZEOF(.data) <= {:#X}, \"{} firmware image extends into its {} at {:#X}\");\n\
+                 ASSERT({:#X} <= ORIGIN(FLASH) + LENGTH(FLASH), \"{} storage ends at {:#X}, after FLASH in memory.x\");\n",
+                image_end, bin, data, image_end, end, bin, end
+            ),
+        )
+        .unwrap();
+        println!("cargo:rustc-link-arg-bin={}=-T{}", bin, script);
+    }
+}
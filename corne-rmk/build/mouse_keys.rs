@@ -0,0 +1,88 @@
+//! The `[mouse_keys]` block.
+
+use crate::config::parse_duration_ms;
+
+/// An acceleration profile of `[mouse_keys]`, as `(delay, interval, step, max_speed,
+/// time_to_max)` with the defaults of `default`
+pub(crate) fn mouse_key_profile(
+    config: &toml::Table,
+    name: &str,
+    default: (u64, u64, u64, u64, u64),
+) -> (u64, u64, u64, u64, u64) {
+    let Some(mouse_keys) = config.get("mouse_keys") else {
+        return default;
+    };
+    let mouse_keys = mouse_keys.as_table().expect("[mouse_keys] must be a table");
+    if let Some(key) = mouse_keys.keys().find(|k| *k != "cursor" && *k != "wheel") {
+        panic!("Unknown setting `{}` in [mouse_keys]", key);
+    }
+    let Some(profile) = mouse_keys.get(name) else {
+        return default;
+    };
+    let profile = profile
+        .as_table()
+        .unwrap_or_else(|| panic!("[mouse_keys.{}] must be a table", name));
+    let duration = |key: &str, default: u64| {
+        profile.get(key).map_or(default, |d| {
+            let ms = parse_duration_ms(d.as_str().unwrap_or_else(|| {
+                panic!("mouse_keys.{}.{} is a string like \"20ms\"", name, key)
+            }));
+            if ms > u64::from(u16::MAX) {
+                panic!("mouse_keys.{}.{} is too long", name, key);
+            }
+            ms
+        })
+    };
+    let number = |key: &str, default: u64| {
+        profile.get(key).map_or(default, |n| {
+            n.as_integer()
+                .filter(|n| (1..=255).contains(n))
+                .unwrap_or_else(|| panic!("mouse_keys.{}.{} is a number from 1 to 255", name, key))
+                as u64
+        })
+    };
+    if let Some(key) = profile
+        .keys()
+        .find(|k| !["delay", "interval", "step", "max_speed", "time_to_max"].contains(&k.as_str()))
+    {
+        panic!("Unknown setting `{}` in [mouse_keys.{}]", key, name);
+    }
+    (
+        duration("delay", default.0),
+        duration("interval", default.1),
+        number("step", default.2),
+        number("max_speed", default.3),
+        number("time_to_max", default.4),
+    )
+}
+
+/// Rust expression of rmk's mouse key settings, from the `[mouse_keys.cursor]`
+/// and `[mouse_keys.wheel]` profiles
+pub(crate) fn mouse_key_config(config: &toml::Table) -> String {
+    let cursor = mouse_key_profile(config, "cursor", (100, 16, 8, 10, 30));
+    let wheel = mouse_key_profile(config, "wheel", (100, 80, 1, 8, 40));
+    format!(
+        "MouseKeyConfig {{
+        initial_delay_ms: {},
+        repeat_interval_ms: {},
+        move_delta: {},
+        max_speed: {},
+        time_to_max: {},
+        wheel_initial_delay_ms: {},
+        wheel_repeat_interval_ms: {},
+        wheel_delta: {},
+        wheel_max_speed_multiplier: {},
+        wheel_time_to_max: {},
+    }}",
+        cursor.0,
+        cursor.1,
+        cursor.2,
+        cursor.3,
+        cursor.4,
+        wheel.0,
+        wheel.1,
+        wheel.2,
+        wheel.3,
+        wheel.4
+    )
+}
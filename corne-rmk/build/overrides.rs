@@ -0,0 +1,249 @@
+//! `[[override]]` blocks, as rmk forks.
+//!
+//! rmk's forks match the action of a key, not its layer. An override limited
+//! to some layers is a fork on every layer whose output does nothing, and the
+//! firmware types it instead, see `overrides.rs` in corne-core.
+
+use crate::config::{
+    keycode, layer_index, layer_names, modifier_bits, parse_matrix_map, rmk_constant,
+    split_function, tokenize_keys,
+};
+use crate::custom_keys::{custom_key_name, custom_keycode, DISPATCHED};
+use crate::keymap::key_action;
+
+/// An `[[override]]` block
+struct Override {
+    description: String,
+    /// Keycode of the trigger
+    trigger: String,
+    output: String,
+    match_any: u8,
+    match_none: u8,
+    keep: bool,
+    /// The layers it's limited to, if any
+    layers: Option<Vec<usize>>,
+}
+
+/// Rust expression of `HidModifiers` with `bits`
+pub(crate) fn hid_modifiers(bits: u8) -> String {
+    format!("HidModifiers::from_bits({:#04x})", bits)
+}
+
+/// Rust expression of `StateBits` that only look at the modifiers in `bits`
+pub(crate) fn modifier_state(bits: u8) -> String {
+    format!(
+        "StateBits::new_from({}, LedIndicator::new(), HidMouseButtons::new())",
+        hid_modifiers(bits)
+    )
+}
+
+/// The keycode of `token` if it's a plain key of rmk's
+fn plain_keycode(token: &str, layer_names: &[String]) -> Option<String> {
+    key_action(token, layer_names)
+        .strip_prefix("k!(")
+        .and_then(|k| k.strip_suffix(')'))
+        .filter(|k| !k.starts_with("User") && *k != "No")
+        .map(str::to_owned)
+}
+
+fn parse_overrides(config: &toml::Table, layer_names: &[String]) -> Vec<Override> {
+    let blocks = config
+        .get("override")
+        .and_then(|o| o.as_array())
+        .map_or(&[][..], |o| o.as_slice());
+    let max_num = rmk_constant(config, "fork_max_num", 8);
+    if blocks.len() > max_num {
+        panic!(
+            "keyboard.toml has {} overrides, but rmk.fork_max_num is {}",
+            blocks.len(),
+            max_num
+        );
+    }
+    let mut overrides: Vec<Override> = Vec::new();
+    for block in blocks {
+        let block = block.as_table().expect("[[override]] is a table");
+        for key in block.keys() {
+            if ![
+                "trigger",
+                "modifiers",
+                "not_modifiers",
+                "output",
+                "keep_modifiers",
+                "layers",
+            ]
+            .contains(&key.as_str())
+            {
+                panic!("Unknown setting `{}` in [[override]]", key);
+            }
+        }
+        let string = |key: &str| {
+            block
+                .get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or_else(|| panic!("Missing {} in [[override]]", key))
+        };
+        let modifiers = |key: &str| -> u8 {
+            block
+                .get(key)
+                .and_then(|m| m.as_array())
+                .map_or(&[][..], |m| m.as_slice())
+                .iter()
+                .map(|m| modifier_bits(m.as_str().expect("Modifiers are strings like \"Shift\"")))
+                .fold(0, |bits, m| bits | m)
+        };
+        let (trigger, output) = (string("trigger"), string("output"));
+        let description = format!("{} -> {}", trigger, output);
+        if split_function(trigger).is_some() {
+            panic!(
+                "The trigger of override {} has to be a plain key",
+                description
+            );
+        }
+        if custom_key_name(output).is_some() || matches!(split_function(output), Some(("SYM", _))) {
+            panic!(
+                "The output of override {} can only be one of rmk's keys, but not SYM(..)",
+                description
+            );
+        }
+        let trigger_key = keycode(trigger);
+        if overrides.iter().any(|o| o.trigger == trigger_key) {
+            panic!("{} has more than one override", trigger);
+        }
+        let match_any = modifiers("modifiers");
+        if match_any == 0 {
+            panic!("Override {} needs modifiers", description);
+        }
+        let layers = block
+            .get("layers")
+            .and_then(|l| l.as_array())
+            .map(|layers| {
+                layers
+                    .iter()
+                    .map(|l| {
+                        layer_index(
+                            l.as_str().expect("Override layers are strings"),
+                            layer_names,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            });
+        if layers.is_some() && plain_keycode(output, layer_names).is_none() {
+            panic!(
+                "Override {} is limited to layers, its output can only be a plain key",
+                description
+            );
+        }
+        overrides.push(Override {
+            description,
+            trigger: trigger_key,
+            output: output.to_owned(),
+            match_any,
+            match_none: modifiers("not_modifiers"),
+            keep: block
+                .get("keep_modifiers")
+                .and_then(|k| k.as_bool())
+                .unwrap_or(false),
+            layers,
+        });
+    }
+    overrides
+}
+
+/// Rust expressions of the `[[override]]` blocks, one rmk fork each.
+///
+/// Each key takes one override, rmk only looks at the first fork of a key.
+pub(crate) fn overrides(config: &toml::Table, layer_names: &[String]) -> String {
+    parse_overrides(config, layer_names)
+        .iter()
+        .map(|o| {
+            let trigger = format!("k!({})", o.trigger);
+            // The firmware types those limited to layers, see `override_tables()`
+            let (output, keep) = match o.layers {
+                Some(_) => (format!("k!({})", custom_keycode(DISPATCHED.0)), true),
+                None => (key_action(&o.output, layer_names), o.keep),
+            };
+            format!(
+                "            // {}\n            Fork::new({}, {}, {}, {}, {}, {}, false),\n",
+                o.description,
+                trigger,
+                trigger,
+                output,
+                modifier_state(o.match_any),
+                modifier_state(o.match_none),
+                hid_modifiers(if keep { o.match_any } else { 0 }),
+            )
+        })
+        .collect()
+}
+
+/// Rust code of `OVERRIDE_KEYS`, the trigger of each override limited to some
+/// layers at each of its positions in the keymap
+pub(crate) fn override_tables(config: &toml::Table) -> String {
+    let layer_names = layer_names(config);
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    let matrix_map = parse_matrix_map(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    let layers = config["layer"].as_array().expect("Missing [[layer]]");
+    let overrides: Vec<Override> = parse_overrides(config, &layer_names)
+        .into_iter()
+        .filter(|o| o.layers.is_some())
+        .collect();
+    let mut entries = String::new();
+    for (layer_index, (layer, layer_name)) in layers.iter().zip(&layer_names).enumerate() {
+        let keys_str = layer["keys"].as_str().expect("Missing keys in [[layer]]");
+        for (token, &(row, col)) in tokenize_keys(keys_str).iter().zip(&matrix_map) {
+            // As rmk's forks match it
+            let action = key_action(token, &layer_names);
+            let Some(o) = overrides
+                .iter()
+                .find(|o| action == format!("k!({})", o.trigger))
+            else {
+                continue;
+            };
+            let layers = o.layers.as_ref().unwrap();
+            let note = if layers.contains(&layer_index) {
+                ""
+            } else {
+                ", not one of its layers"
+            };
+            entries += &format!(
+                "    // {} on {}{}\n    OverrideKey {{\n        layer: {},\n        pos: ({}, {}),\n        rule: LayerOverride {{\n            layers: {:#x},\n            trigger: KeyCode::{} as u8,\n            output: KeyCode::{} as u8,\n            modifiers: {:#04x},\n            keep_modifiers: {},\n        }},\n    }},\n",
+                o.description,
+                layer_name,
+                note,
+                layer_index,
+                row,
+                col,
+                layers.iter().fold(0u32, |mask, l| mask | 1 << l),
+                o.trigger,
+                plain_keycode(&o.output, &layer_names).unwrap(),
+                o.match_any,
+                o.keep
+            );
+        }
+    }
+    // The firmware types them in keyboard reports of its own
+    let checks: String = overrides
+        .iter()
+        .flat_map(|o| {
+            [
+                o.trigger.clone(),
+                plain_keycode(&o.output, &layer_names).unwrap(),
+            ]
+        })
+        .map(|key| {
+            format!(
+                "const _: () = assert!(KeyCode::{key} as u16 >= KeyCode::A as u16 && KeyCode::{key} as u16 <= 0xA4, \"{key} isn't a key of the keyboard report\");\n"
+            )
+        })
+        .collect();
+    format!(
+        "pub(crate) const OVERRIDE_KEYS: [OverrideKey; {}] = [\n{}];\n\n{}",
+        entries.matches("OverrideKey {").count(),
+        entries,
+        checks
+    )
+}
@@ -0,0 +1,151 @@
+//! Checks of the pins that `central.rs` and `peripheral.rs` use.
+
+use std::fs;
+
+use crate::config::read_keyboard_toml;
+
+/// Pins of one half
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct HalfPins {
+    pub(crate) input: Vec<String>,
+    pub(crate) output: Vec<String>,
+    pub(crate) analog: Vec<String>,
+}
+
+pub(crate) fn toml_pin_list(table: &toml::Value, key: &str, half: &str) -> Vec<String> {
+    table
+        .get(key)
+        .and_then(|p| p.as_array())
+        .unwrap_or_else(|| panic!("Missing {} of {} in keyboard.toml", key, half))
+        .iter()
+        .map(|p| p.as_str().expect("Pins must be strings").to_owned())
+        .collect()
+}
+
+/// Pins of a `[split.central]` or `[[split.peripheral]]` block
+pub(crate) fn toml_half_pins(half: &toml::Value, name: &str) -> HalfPins {
+    let matrix = half
+        .get("matrix")
+        .unwrap_or_else(|| panic!("Missing matrix of {} in keyboard.toml", name));
+    let analog = half
+        .get("input_device")
+        .and_then(|d| d.get("joystick"))
+        .and_then(|j| j.as_array())
+        .map(|joysticks| {
+            joysticks
+                .iter()
+                .flat_map(|j| ["pin_x", "pin_y"].map(|k| j.get(k).and_then(|p| p.as_str())))
+                .map(|p| p.expect("Joystick pins must be strings").to_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    HalfPins {
+        input: toml_pin_list(matrix, "input_pins", name),
+        output: toml_pin_list(matrix, "output_pins", name),
+        analog,
+    }
+}
+
+/// Remove `//` comments, so that commented out pins are ignored
+pub(crate) fn strip_comments(source: &str) -> String {
+    source
+        .lines()
+        .map(|l| l.split("//").next().unwrap())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Pin names in the `[...]` list that follows `key`
+pub(crate) fn source_pin_list(source: &str, key: &str, file: &str) -> Vec<String> {
+    let start = source
+        .find(key)
+        .unwrap_or_else(|| panic!("Cannot find `{}` in {}", key, file))
+        + key.len();
+    let list = &source[start..];
+    let list = &list[list.find('[').unwrap() + 1..list.find(']').unwrap()];
+    list.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Pins that a binary passes to `config_matrix_pins_nrf!` and the SAADC
+pub(crate) fn source_half_pins(file: &str) -> HalfPins {
+    let source = strip_comments(
+        &fs::read_to_string(file).unwrap_or_else(|_| panic!("Cannot read {}", file)),
+    );
+    let matrix = &source[source
+        .find("config_matrix_pins_nrf!(")
+        .unwrap_or_else(|| panic!("Cannot find the matrix pins in {}", file))..];
+    let analog = source
+        .match_indices(".degrade_saadc()")
+        .filter_map(|(i, _)| {
+            let pin = &source[..i];
+            let pin = &pin[pin.rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))? + 1..];
+            // Internal inputs like `VddhDiv5Input` are not pins
+            (pin.starts_with('P') && pin.contains('_')).then(|| pin.to_owned())
+        })
+        .collect();
+    HalfPins {
+        input: source_pin_list(matrix, "input:", file),
+        output: source_pin_list(matrix, "output:", file),
+        analog,
+    }
+}
+
+/// Every pin must be used once per half, and the binaries must use the pins in keyboard.toml
+pub(crate) fn check_half_pins(name: &str, file: &str, expected: &HalfPins) {
+    let actual = source_half_pins(file);
+    let mut pins: Vec<&String> = expected
+        .input
+        .iter()
+        .chain(&expected.output)
+        .chain(&expected.analog)
+        .collect();
+    pins.sort();
+    for pair in pins.windows(2) {
+        if pair[0] == pair[1] {
+            panic!("Pin {} is used more than once by the {}", pair[0], name);
+        }
+    }
+    if actual.input != expected.input {
+        panic!(
+            "Input pins of the {} disagree: {} uses {:?}, keyboard.toml says {:?}",
+            name, file, actual.input, expected.input
+        );
+    }
+    if actual.output != expected.output {
+        panic!(
+            "Output pins of the {} disagree: {} uses {:?}, keyboard.toml says {:?}",
+            name, file, actual.output, expected.output
+        );
+    }
+    if actual.analog != expected.analog {
+        panic!(
+            "Analog pins of the {} disagree: {} uses {:?}, keyboard.toml says {:?}",
+            name, file, actual.analog, expected.analog
+        );
+    }
+}
+
+pub(crate) fn check_pins() {
+    let config = read_keyboard_toml();
+    let split = config.get("split").expect("Missing [split]");
+    let central = split.get("central").expect("Missing [split.central]");
+    check_half_pins(
+        "central",
+        "src/central.rs",
+        &toml_half_pins(central, "split.central"),
+    );
+    let peripheral = split
+        .get("peripheral")
+        .and_then(|p| p.as_array())
+        .and_then(|p| p.first())
+        .expect("Missing [[split.peripheral]]");
+    check_half_pins(
+        "peripheral",
+        "src/peripheral.rs",
+        &toml_half_pins(peripheral, "split.peripheral"),
+    );
+}
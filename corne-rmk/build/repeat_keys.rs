@@ -0,0 +1,121 @@
+//! The `[repeat]` block, the Repeat and Alt-Repeat keys.
+
+use corne_core::host_layout::HostLayout;
+
+use crate::config::{keycode, split_function};
+use crate::custom_keys::{custom_key_name, CustomKey};
+use crate::macros::macro_count;
+use crate::symbols::symbol_char;
+
+/// Types the last key or character again, see `src/repeat_keys.rs`
+pub(crate) const REPEAT: &str = "REPEAT";
+
+/// The custom key of `REPEAT`, every macro goes on a hidden key from
+/// `next_hidden` to run it again
+pub(crate) fn repeat_key(
+    config: &toml::Table,
+    next_hidden: &mut dyn FnMut() -> (usize, usize),
+) -> CustomKey {
+    let hidden_actions = (0..macro_count(config))
+        .map(|i| (next_hidden(), format!("k!(Macro{})", i)))
+        .collect();
+    CustomKey {
+        name: REPEAT.to_owned(),
+        action: "CustomKey::Repeat".to_owned(),
+        hidden_actions,
+    }
+}
+
+/// Rust expression of the `Keystroke` that `token` of `[repeat] alternates`
+/// types on the host's `layout`
+pub(crate) fn repeat_keystroke(token: &str, layout: HostLayout) -> String {
+    if let Some(("SYM", args)) = split_function(token) {
+        let c = symbol_char(args[0]);
+        let keystroke = layout.keystroke(c).filter(|k| !k.dead).unwrap_or_else(|| {
+            panic!(
+                "The {:?} host layout has no `{}` without a dead key, used in [repeat]",
+                layout, c
+            )
+        });
+        return format!(
+            "Keystroke {{ key: {:#04x}, modifiers: {:#04x} }}",
+            keystroke.key, keystroke.modifiers
+        );
+    }
+    if custom_key_name(token).is_some() || split_function(token).is_some() {
+        panic!(
+            "[repeat] alternates can only be SYM(..) or one of rmk's keys, not `{}`",
+            token
+        );
+    }
+    format!(
+        "Keystroke {{ key: KeyCode::{} as u8, modifiers: 0 }}",
+        keycode(token)
+    )
+}
+
+/// Rust code of the Alt-Repeat pairs for each host layout and the hidden keys
+/// of the macros
+pub(crate) fn repeat_tables(config: &toml::Table, custom_keys: &[CustomKey]) -> String {
+    let pairs: Vec<(&str, &str)> = match config.get("repeat") {
+        None => Vec::new(),
+        Some(repeat) => {
+            let repeat = repeat.as_table().expect("[repeat] must be a table");
+            if let Some(key) = repeat.keys().find(|k| *k != "alternates") {
+                panic!("Unknown setting `{}` in [repeat]", key);
+            }
+            repeat
+                .get("alternates")
+                .and_then(|a| a.as_array())
+                .map_or(&[][..], |a| a.as_slice())
+                .iter()
+                .map(|pair| {
+                    match pair
+                        .as_array()
+                        .map(|p| p.iter().map(|k| k.as_str()).collect::<Vec<_>>())
+                        .as_deref()
+                    {
+                        Some([Some(a), Some(b)]) => (*a, *b),
+                        _ => {
+                            panic!("[repeat] alternates are pairs of keys like [\"Up\", \"Down\"]")
+                        }
+                    }
+                })
+                .collect()
+        }
+    };
+    let layouts: String = HostLayout::ALL
+        .iter()
+        .map(|&layout| {
+            let entries: String = pairs
+                .iter()
+                .map(|(a, b)| {
+                    format!(
+                        "        // {} and {}\n        ({}, {}),\n",
+                        a,
+                        b,
+                        repeat_keystroke(a, layout),
+                        repeat_keystroke(b, layout)
+                    )
+                })
+                .collect();
+            format!("    // {:?}\n    [\n{}    ],\n", layout, entries)
+        })
+        .collect();
+    let macro_keys: Vec<String> = custom_keys
+        .iter()
+        .find(|k| k.name == REPEAT)
+        .map_or(&[][..], |k| k.hidden_actions.as_slice())
+        .iter()
+        .map(|((row, col), _)| format!("({}, {})", row, col))
+        .collect();
+    format!(
+        "pub(crate) static ALTERNATES: [[(Keystroke, Keystroke); {}]; {}] = [\n{}];\n\n\
+         pub(crate) const MACRO_KEYS: [(u8, u8); {}] = [{}];\n",
+        pairs.len(),
+        HostLayout::ALL.len(),
+        layouts,
+        macro_keys.len(),
+        macro_keys.join(", ")
+    )
+}
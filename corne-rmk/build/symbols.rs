@@ -0,0 +1,42 @@
+//! `SYM(..)` keys, the symbols typed with the host's keyboard layout.
+
+use corne_core::host_layout::HostLayout;
+use corne_core::usage;
+
+use crate::config::usage_keycode;
+
+/// The character of `SYM($)`, with names for those that would confuse the parser
+pub(crate) fn symbol_char(arg: &str) -> char {
+    match arg {
+        "LeftParen" => '(',
+        "RightParen" => ')',
+        "Comma" => ',',
+        _ => {
+            let mut chars = arg.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => panic!("Invalid symbol `SYM({})` in keyboard.toml", arg),
+            }
+        }
+    }
+}
+
+/// `KeyAction` that types `c` on a host set to `layout`
+pub(crate) fn symbol_action(c: char, layout: HostLayout) -> String {
+    let keystroke = layout.keystroke(c).unwrap_or_else(|| {
+        panic!(
+            "The {:?} host layout has no `{}`, used in keyboard.toml",
+            layout, c
+        )
+    });
+    let key = usage_keycode(keystroke.key);
+    match keystroke.modifiers {
+        0 => format!("k!({})", key),
+        usage::MOD_LSHIFT => format!("shifted!({})", key),
+        usage::MOD_RALT => format!(
+            "wm!({}, ModifierCombination::new_from(true, false, true, false, false))",
+            key
+        ),
+        m => panic!("Unsupported modifiers {:#04X} for `{}`", m, c),
+    }
+}
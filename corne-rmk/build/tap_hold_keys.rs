@@ -0,0 +1,185 @@
+//! The `[tap_hold]`, `[tap_dance]` and `[one_shot]` blocks.
+
+use crate::config::{
+    keycode, layer_index, layer_names, parse_duration_ms, section_timeout_ms, split_function,
+};
+use crate::custom_keys::{custom_key_name, CustomKey};
+use crate::keymap::key_action;
+
+/// Rust expression of the `[tap_hold.<name>]` profile
+pub(crate) fn tap_hold_profile(name: &str, config: &toml::Table) -> String {
+    let profile = config
+        .get("tap_hold")
+        .and_then(|t| t.get(name))
+        .and_then(|p| p.as_table())
+        .unwrap_or_else(|| panic!("No [tap_hold.{}] in keyboard.toml", name));
+    for key in profile.keys() {
+        if ![
+            "hold_timeout",
+            "quick_tap",
+            "permissive_hold",
+            "hold_on_other_press",
+        ]
+        .contains(&key.as_str())
+        {
+            panic!("Unknown setting `{}` in [tap_hold.{}]", key, name);
+        }
+    }
+    let duration = |key: &str, default: &str| {
+        parse_duration_ms(
+            profile
+                .get(key)
+                .map(|d| {
+                    d.as_str()
+                        .expect("Tap-hold times are strings like \"200ms\"")
+                })
+                .unwrap_or(default),
+        )
+    };
+    let flag = |key: &str| profile.get(key).and_then(|f| f.as_bool()).unwrap_or(false);
+    format!(
+        "corne_core::tap_hold::Profile {{ hold_timeout_ms: {}, quick_tap_ms: {}, permissive_hold: {}, hold_on_other_press: {} }}",
+        duration("hold_timeout", "200ms"),
+        duration("quick_tap", "0ms"),
+        flag("permissive_hold"),
+        flag("hold_on_other_press"),
+    )
+}
+
+/// The custom key of `TH(Tap, Hold, profile)` or `LT(Layer, Key, profile)`, whose
+/// actions go on hidden keys from `next_hidden`
+pub(crate) fn tap_hold_key(
+    name: &str,
+    config: &toml::Table,
+    next_hidden: &mut dyn FnMut() -> (usize, usize),
+) -> CustomKey {
+    let (tap, hold) = (next_hidden(), next_hidden());
+    let (function, args) = split_function(name).unwrap();
+    let (tap_key, hold_action, profile) = match (function, args.as_slice()) {
+        ("TH", [tap, hold, profile]) => (keycode(tap), format!("k!({})", keycode(hold)), profile),
+        ("LT", [layer, key, profile]) => (
+            keycode(key),
+            format!("mo!({})", layer_index(layer, &layer_names(config))),
+            profile,
+        ),
+        _ => unreachable!(),
+    };
+    CustomKey {
+        name: name.to_owned(),
+        action: format!(
+            "CustomKey::TapHold(TapHoldKey {{ profile: {}, tap: ({}, {}), hold: ({}, {}) }})",
+            tap_hold_profile(profile, config),
+            tap.0,
+            tap.1,
+            hold.0,
+            hold.1
+        ),
+        hidden_actions: vec![(tap, format!("k!({})", tap_key)), (hold, hold_action)],
+    }
+}
+
+/// Modifiers that `OSM(..)` takes
+pub(crate) const ONE_SHOT_MODIFIERS: [&str; 8] = [
+    "LShift", "LCtrl", "LAlt", "LGui", "RShift", "RCtrl", "RAlt", "RGui",
+];
+
+/// The custom key of `OSM(Modifier)` or `OSL(Layer)`, which goes on a hidden
+/// key from `next_hidden`
+pub(crate) fn one_shot_key(
+    name: &str,
+    config: &toml::Table,
+    next_hidden: &mut dyn FnMut() -> (usize, usize),
+) -> CustomKey {
+    let hidden = next_hidden();
+    let (function, args) = split_function(name).unwrap();
+    let action = match function {
+        "OSM" if ONE_SHOT_MODIFIERS.contains(&args[0]) => format!("k!({})", args[0]),
+        "OSM" => panic!("`{}` in keyboard.toml isn't a modifier", name),
+        _ => format!("mo!({})", layer_index(args[0], &layer_names(config))),
+    };
+    CustomKey {
+        name: name.to_owned(),
+        action: format!(
+            "CustomKey::OneShot(OneShotKey {{ timeout_ms: {}, hidden: {:?} }})",
+            section_timeout_ms(config, "one_shot", &[], "1s"),
+            hidden
+        ),
+        hidden_actions: vec![(hidden, action)],
+    }
+}
+
+/// Actions of the `[tap_dance.<name>]` block, as `(setting, token)`
+pub(crate) fn tap_dance_actions<'a>(
+    name: &str,
+    config: &'a toml::Table,
+) -> (&'a toml::Table, Vec<(&'a str, &'a str)>) {
+    let dance = config
+        .get("tap_dance")
+        .and_then(|t| t.get(name))
+        .and_then(|d| d.as_table())
+        .unwrap_or_else(|| panic!("No [tap_dance.{}] in keyboard.toml", name));
+    let mut actions = Vec::new();
+    for (key, value) in dance {
+        match key.as_str() {
+            "term" => {}
+            "tap" | "double_tap" | "hold" | "tap_hold" => {
+                let token = value.as_str().unwrap_or_else(|| {
+                    panic!("tap_dance.{}.{} is a key like \"Escape\"", name, key)
+                });
+                if custom_key_name(token).is_some() {
+                    panic!("tap_dance.{}.{} can only be one of rmk's keys", name, key);
+                }
+                actions.push((key.as_str(), token));
+            }
+            _ => panic!("Unknown setting `{}` in [tap_dance.{}]", key, name),
+        }
+    }
+    if !actions.iter().any(|(key, _)| *key == "tap") {
+        panic!("Missing tap in [tap_dance.{}]", name);
+    }
+    (dance, actions)
+}
+
+/// Hidden keys that the tap-dance key `name` needs
+pub(crate) fn tap_dance_hidden_keys(name: &str, config: &toml::Table) -> usize {
+    tap_dance_actions(name, config).1.len()
+}
+
+/// The custom key of `TD(name)`, whose actions go on hidden keys from `next_hidden`
+pub(crate) fn tap_dance_key(
+    name: &str,
+    config: &toml::Table,
+    next_hidden: &mut dyn FnMut() -> (usize, usize),
+) -> CustomKey {
+    let dance_name = &name["TD(".len()..name.len() - 1];
+    let (dance, actions) = tap_dance_actions(dance_name, config);
+    let term = parse_duration_ms(
+        dance
+            .get("term")
+            .map(|t| {
+                t.as_str()
+                    .expect("Tap-dance terms are strings like \"200ms\"")
+            })
+            .unwrap_or("200ms"),
+    );
+    let layer_names = layer_names(config);
+    let mut hidden_actions = Vec::new();
+    let mut position = |setting: &str| {
+        let (_, token) = actions.iter().find(|(key, _)| *key == setting)?;
+        let pos = next_hidden();
+        hidden_actions.push((pos, key_action(token, &layer_names)));
+        Some(pos)
+    };
+    let tap = position("tap").unwrap();
+    let double_tap = position("double_tap");
+    let hold = position("hold").unwrap_or(tap);
+    let tap_hold = position("tap_hold");
+    CustomKey {
+        name: name.to_owned(),
+        action: format!(
+            "CustomKey::TapDance(TapDanceKey {{ term_ms: {}, tap: {:?}, double_tap: {:?}, hold: {:?}, tap_hold: {:?} }})",
+            term, tap, double_tap, hold, tap_hold
+        ),
+        hidden_actions,
+    }
+}
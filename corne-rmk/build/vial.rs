@@ -0,0 +1,148 @@
+//! The Vial layout definition.
+
+use std::io::Read;
+use std::path::Path;
+use std::{env, fs};
+
+use const_gen::*;
+use xz2::read::XzEncoder;
+
+use crate::config::{parse_matrix_rows, read_keyboard_toml};
+use crate::custom_keys::{DISPATCHED, FIXED_KEYS, RMK_CUSTOM_KEYCODES};
+use crate::hidden_keys::keymap_rows;
+
+/// Gap between the two halves in the Vial layout, in key widths
+pub(crate) const VIAL_SPLIT_GAP: usize = 1;
+
+/// Vial's KLE layout, one row per line of `matrix_map`.
+///
+/// Keys are placed at their matrix column, with a gap between the halves.
+pub(crate) fn vial_layout(
+    matrix_rows: &[Vec<(usize, usize)>],
+    split_col: usize,
+) -> json::JsonValue {
+    let mut layout = json::JsonValue::new_array();
+    for line in matrix_rows {
+        let mut keys: Vec<(usize, (usize, usize))> = line
+            .iter()
+            .map(|&(row, col)| {
+                let gap = if col >= split_col { VIAL_SPLIT_GAP } else { 0 };
+                (col + gap, (row, col))
+            })
+            .collect();
+        keys.sort();
+
+        let mut kle_row = json::JsonValue::new_array();
+        let mut cursor = 0;
+        for (x, (row, col)) in keys {
+            if x > cursor {
+                kle_row.push(json::object! { "x": x - cursor }).unwrap();
+            }
+            kle_row.push(format!("{},{}", row, col)).unwrap();
+            cursor = x + 1;
+        }
+        layout.push(kle_row).unwrap();
+    }
+    layout
+}
+
+/// 64 bit FNV-1a, which unlike `DefaultHasher` is stable across Rust versions
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Vial keyboard ID derived from the name and USB IDs in `[keyboard]`,
+/// so that boards with a different config don't share it
+pub(crate) fn vial_keyboard_id(keyboard: &toml::Table) -> u64 {
+    let field = |key: &str| {
+        keyboard
+            .get(key)
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| panic!("Missing keyboard.{}", key))
+    };
+    let seed = format!(
+        "{}:{}:{}",
+        field("name"),
+        field("vendor_id"),
+        field("product_id")
+    );
+    fnv1a(seed.as_bytes())
+}
+
+pub(crate) fn generate_vial_config() {
+    // Generated vial config file
+    let out_file = Path::new(&env::var_os("OUT_DIR").unwrap()).join("config_generated.rs");
+
+    let config = read_keyboard_toml();
+    let keyboard = config["keyboard"].as_table().expect("Missing [keyboard]");
+    let layout = config["layout"].as_table().expect("Missing [layout]");
+    // Vial transfers the whole keymap, hidden keys included
+    let rows = keymap_rows(&config);
+    let cols = layout["cols"].as_integer().expect("Missing layout.cols");
+    let matrix_rows = parse_matrix_rows(
+        layout["matrix_map"]
+            .as_str()
+            .expect("Missing layout.matrix_map"),
+    );
+    // The right half starts at the peripheral's column offset
+    let split_col = config
+        .get("split")
+        .and_then(|s| s.get("peripheral"))
+        .and_then(|p| p.as_array())
+        .and_then(|p| p.first())
+        .and_then(|p| p.get("col_offset"))
+        .and_then(|c| c.as_integer())
+        .unwrap_or(cols / 2) as usize;
+
+    let mut custom_keycodes = json::JsonValue::new_array();
+    for (name, title, short_name) in RMK_CUSTOM_KEYCODES {
+        custom_keycodes
+            .push(json::object! {
+                "name": name,
+                "title": title,
+                "shortName": short_name,
+            })
+            .unwrap();
+    }
+    for (name, title, short_name) in FIXED_KEYS.into_iter().chain([DISPATCHED]) {
+        custom_keycodes
+            .push(json::object! {
+                "name": name,
+                "title": title,
+                "shortName": short_name,
+            })
+            .unwrap();
+    }
+    let id = |key: &str| {
+        let id = keyboard[key]
+            .as_integer()
+            .unwrap_or_else(|| panic!("Missing keyboard.{}", key));
+        format!("0x{:04X}", id)
+    };
+    let vial_cfg = json::object! {
+        "name": keyboard["name"].as_str().expect("Missing keyboard.name"),
+        "vendorId": id("vendor_id"),
+        "productId": id("product_id"),
+        "lighting": "none",
+        "matrix": { "rows": rows, "cols": cols },
+        "customKeycodes": custom_keycodes,
+        "layouts": { "keymap": vial_layout(&matrix_rows, split_col) },
+    };
+
+    let vial_cfg = json::stringify(vial_cfg);
+    let mut keyboard_def_compressed: Vec<u8> = Vec::new();
+    XzEncoder::new(vial_cfg.as_bytes(), 6)
+        .read_to_end(&mut keyboard_def_compressed)
+        .unwrap();
+
+    let keyboard_id = vial_keyboard_id(keyboard).to_le_bytes().to_vec();
+    let const_declarations = [
+        const_declaration!(pub VIAL_KEYBOARD_DEF = keyboard_def_compressed),
+        const_declaration!(pub VIAL_KEYBOARD_ID = keyboard_id),
+    ]
+    .map(|s| "#[allow(clippy::redundant_static_lifetimes)]\n".to_owned() + s.as_str())
+    .join("\n");
+    fs::write(out_file, const_declarations).unwrap();
+}
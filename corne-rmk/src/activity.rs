@@ -0,0 +1,85 @@
+//! User activity that isn't a keypress, e.g. moving a joystick.
+//!
+//! rmk is told with a key event of `ACTIVITY_KEY`, a hidden key that does
+//! nothing. To rmk it is a key event like those of the matrix, so it resets its idle and
+//! sleep timers, and the peripheral sends it to the central over the split
+//! link. Only releases are sent, nothing is pressed that rmk's tap-hold keys
+//! would take for another key, and at most one every `KEY_EVENT_INTERVAL`.
+//!
+//! The time of the last activity is kept here as well, for everything else
+//! that depends on whether the user is idle.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant};
+use rmk::channel::KEY_EVENT_CHANNEL;
+use rmk::event::KeyboardEvent;
+
+/// Shortest time between two key events of `ACTIVITY_KEY`
+const KEY_EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+static LAST_ACTIVITY: Mutex<CriticalSectionRawMutex, Cell<Instant>> =
+    Mutex::new(Cell::new(Instant::from_ticks(0)));
+
+static LAST_KEY_EVENT: Mutex<CriticalSectionRawMutex, Cell<Instant>> =
+    Mutex::new(Cell::new(Instant::from_ticks(0)));
+
+static ACTIVITY_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+static POINTER_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Record that the user is doing something, and tell rmk with a release of
+/// `activity_key`, the position of `ACTIVITY_KEY` as this half sends it
+pub(crate) fn report_activity(activity_key: (u8, u8)) {
+    let now = Instant::now();
+    LAST_ACTIVITY.lock(|t| t.set(now));
+    ACTIVITY_SIGNAL.signal(());
+    let due = LAST_KEY_EVENT.lock(|t| {
+        let due = now.duration_since(t.get()) >= KEY_EVENT_INTERVAL;
+        if due {
+            t.set(now);
+        }
+        due
+    });
+    if due {
+        // The next one is soon enough if rmk is busy
+        let _ =
+            KEY_EVENT_CHANNEL.try_send(KeyboardEvent::key(activity_key.0, activity_key.1, false));
+    }
+}
+
+/// Time since the last reported activity
+pub(crate) fn idle_time() -> Duration {
+    LAST_ACTIVITY.lock(|t| t.get()).elapsed()
+}
+
+/// Wait until activity is reported
+pub(crate) async fn wait_for_activity() {
+    ACTIVITY_SIGNAL.wait().await
+}
+
+/// Forget activity that nobody waited for
+pub(crate) fn forget_activity() {
+    ACTIVITY_SIGNAL.reset();
+}
+
+/// Record that a joystick moved the pointer or scrolled, see `report_activity()`
+pub(crate) fn report_pointer_use(activity_key: (u8, u8)) {
+    report_activity(activity_key);
+    POINTER_SIGNAL.signal(());
+}
+
+/// Forget pointer use that nobody waited for
+#[allow(dead_code)] // Only the central's custom keys wait for it
+pub(crate) fn forget_pointer_use() {
+    POINTER_SIGNAL.reset();
+}
+
+/// Wait until pointer use is reported
+#[allow(dead_code)]
+pub(crate) async fn wait_for_pointer_use() {
+    POINTER_SIGNAL.wait().await
+}
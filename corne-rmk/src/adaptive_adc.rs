@@ -0,0 +1,50 @@
+use embassy_time::{Duration, Timer};
+use rmk::event::Event;
+use rmk::futures::future::select;
+use rmk::input_device::InputDevice;
+
+use crate::activity;
+
+/// Wraps an ADC device that polls at its fast rate, and slows it down once
+/// no joystick has moved for `rest_timeout`.
+///
+/// Activity is only reported by the joysticks, see `activity.rs`, keypresses
+/// don't count. Reported activity cuts the slow wait short, and a stick that
+/// starts moving is picked up by the next slow poll at the latest.
+pub struct AdaptiveAdc<D: InputDevice> {
+    inner: D,
+    /// Extra delay before every reading while at rest
+    slow_interval: Duration,
+    rest_timeout: Duration,
+    resting: bool,
+}
+
+impl<D: InputDevice> AdaptiveAdc<D> {
+    pub fn new(inner: D, slow_interval: Duration, rest_timeout: Duration) -> Self {
+        Self {
+            inner,
+            slow_interval,
+            rest_timeout,
+            resting: false,
+        }
+    }
+}
+
+impl<D: InputDevice> InputDevice for AdaptiveAdc<D> {
+    async fn read_event(&mut self) -> Event {
+        let resting = activity::idle_time() > self.rest_timeout;
+        if resting && !self.resting {
+            // Activity from before the rest would cut the first slow wait short
+            activity::forget_activity();
+        }
+        self.resting = resting;
+        if resting {
+            select(
+                Timer::after(self.slow_interval),
+                activity::wait_for_activity(),
+            )
+            .await;
+        }
+        self.inner.read_event().await
+    }
+}
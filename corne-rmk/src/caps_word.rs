@@ -0,0 +1,119 @@
+//! Caps Word, capital letters until the word ends.
+//!
+//! It turns on the host's Caps Lock with a hidden key and turns it off again
+//! when a key that doesn't belong to a word is pressed, when the joystick is
+//! used or after a while without typing.
+
+use embassy_time::{Duration, Instant};
+use rmk::action::{Action, KeyAction};
+use rmk::channel::KEY_EVENT_CHANNEL;
+use rmk::event::{KeyboardEvent, KeyboardEventPos};
+use rmk::keycode::KeyCode;
+
+/// The Caps Word key and the hidden key with Caps Lock, as `(row, col)`
+#[derive(Clone, Copy)]
+pub(crate) struct CapsWordKey {
+    /// Caps Word ends after this long without a key of the word
+    pub(crate) timeout_ms: u64,
+    pub(crate) caps_lock: (u8, u8),
+}
+
+/// How a key affects a word in capitals
+pub(crate) enum WordKey {
+    /// Part of the word
+    Word,
+    /// Ends the word
+    Other,
+    /// Neither, like modifiers and layer keys
+    Ignored,
+}
+
+impl WordKey {
+    pub(crate) fn of(action: KeyAction) -> Self {
+        let KeyAction::Single(Action::Key(keycode)) = action else {
+            return WordKey::Ignored;
+        };
+        let code = keycode as u16;
+        let within = |first: KeyCode, last: KeyCode| (first as u16..=last as u16).contains(&code);
+        if within(KeyCode::A, KeyCode::Z)
+            || within(KeyCode::Kc1, KeyCode::Kc0)
+            || matches!(
+                keycode,
+                KeyCode::Minus | KeyCode::Backspace | KeyCode::Delete
+            )
+        {
+            WordKey::Word
+        } else if within(KeyCode::LCtrl, KeyCode::RGui) || keycode == KeyCode::No {
+            WordKey::Ignored
+        } else {
+            WordKey::Other
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct CapsWord {
+    /// The key while Caps Word is on, with the time of the last key of the word
+    active: Option<(CapsWordKey, Instant)>,
+}
+
+impl CapsWord {
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// When Caps Word ends unless the word goes on
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        let (key, last) = self.active?;
+        Some(last + Duration::from_millis(key.timeout_ms))
+    }
+
+    /// The Caps Word `key` was pressed
+    pub(crate) async fn toggle(&mut self, key: CapsWordKey) {
+        if self.is_active() {
+            self.stop().await;
+            return;
+        }
+        tap(key.caps_lock).await;
+        self.active = Some((key, Instant::now()));
+    }
+
+    /// A key was pressed, `word` tells how it affects the word
+    pub(crate) async fn key_pressed(&mut self, event: &KeyboardEvent, word: WordKey) {
+        let Some((key, last)) = self.active.as_mut() else {
+            return;
+        };
+        if let KeyboardEventPos::Key(pos) = event.pos {
+            // Its own Caps Lock
+            if (pos.row, pos.col) == key.caps_lock {
+                return;
+            }
+        }
+        match word {
+            WordKey::Word => *last = Instant::now(),
+            WordKey::Other => self.stop().await,
+            WordKey::Ignored => {}
+        }
+    }
+
+    /// End Caps Word if it has waited for long enough
+    pub(crate) async fn timeout(&mut self) {
+        if self.deadline().is_some_and(|d| d <= Instant::now()) {
+            self.stop().await;
+        }
+    }
+
+    pub(crate) async fn stop(&mut self) {
+        if let Some((key, _)) = self.active.take() {
+            tap(key.caps_lock).await;
+        }
+    }
+}
+
+async fn tap(pos: (u8, u8)) {
+    for pressed in [true, false] {
+        KEY_EVENT_CHANNEL
+            .send(KeyboardEvent::key(pos.0, pos.1, pressed))
+            .await;
+    }
+}
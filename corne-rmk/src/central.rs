@@ -4,10 +4,29 @@
 mod vial;
 #[macro_use]
 mod macros;
+mod activity;
+mod adaptive_adc;
+mod caps_word;
+mod custom_keys;
 mod joystick;
 mod keymap;
+mod layers;
+mod leader_keys;
+mod memory;
+mod mouse;
+mod overrides;
+mod raw_hid;
+mod repeat_keys;
+mod settings;
+mod symbols;
+mod tap_hold_keys;
+mod tuning;
 
+use crate::adaptive_adc::AdaptiveAdc;
+use crate::custom_keys::CustomKeyController;
 use crate::keymap::{COL, COL_OFFSET, NUM_ENCODER, NUM_LAYER, ROW};
+use crate::settings::{SettingsStore, SharedFlash};
+use corne_core::protocol;
 use defmt::{info, unwrap};
 use embassy_executor::Spawner;
 use embassy_nrf::gpio::{Input, Output};
@@ -18,6 +37,8 @@ use embassy_nrf::saadc::{self, AnyInput, Input as _, Saadc};
 use embassy_nrf::usb::vbus_detect::HardwareVbusDetect;
 use embassy_nrf::usb::Driver;
 use embassy_nrf::{bind_interrupts, rng, usb, Peri};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
 use embassy_time::Duration;
 use nrf_mpsl::Flash;
 use nrf_sdc::mpsl::MultiprotocolServiceLayer;
@@ -30,8 +51,9 @@ use rmk::config::macro_config::KeyboardMacrosConfig;
 use rmk::config::{
     BehaviorConfig, BleBatteryConfig, KeyboardUsbConfig, RmkConfig, StorageConfig, VialConfig,
 };
+use rmk::controller::EventController;
 use rmk::debounce::default_debouncer::DefaultDebouncer;
-use rmk::futures::future::{join, join4};
+use rmk::futures::future::{join4, join5};
 use rmk::input_device::adc::{AnalogEventType, NrfAdc};
 use rmk::input_device::battery::BatteryProcessor;
 use rmk::input_device::joystick::JoystickProcessor;
@@ -60,6 +82,8 @@ bind_interrupts!(struct Irqs {
     RTC0 => nrf_sdc::mpsl::HighPrioInterruptHandler;
 });
 
+type UsbDriver = Driver<'static, USBD, HardwareVbusDetect>;
+
 #[embassy_executor::task]
 async fn mpsl_task(mpsl: &'static MultiprotocolServiceLayer<'static>) -> ! {
     mpsl.run().await
@@ -96,14 +120,34 @@ fn build_sdc<'d, const N: usize>(
         .build(p, rng, mpsl, mem)
 }
 
-fn ble_addr() -> [u8; 6] {
+/// Unique ID of this chip
+fn device_id() -> u64 {
     let ficr = embassy_nrf::pac::FICR;
     let high = u64::from(ficr.deviceid(1).read());
-    let addr = high << 32 | u64::from(ficr.deviceid(0).read());
-    let addr = addr | 0x0000_c000_0000_0000;
+    high << 32 | u64::from(ficr.deviceid(0).read())
+}
+
+fn ble_addr() -> [u8; 6] {
+    let addr = device_id() | 0x0000_c000_0000_0000;
     unwrap!(addr.to_le_bytes()[..6].try_into())
 }
 
+/// Vial's serial number magic
+const SERIAL_PREFIX: &[u8] = b"vial:f64c2b3c:";
+
+/// USB serial number, Vial's magic followed by the device ID in hex,
+/// so that two boards can be told apart by the host
+fn serial_number() -> &'static str {
+    static SERIAL: StaticCell<[u8; SERIAL_PREFIX.len() + 16]> = StaticCell::new();
+    let serial = SERIAL.init([0; SERIAL_PREFIX.len() + 16]);
+    serial[..SERIAL_PREFIX.len()].copy_from_slice(SERIAL_PREFIX);
+    let id = device_id();
+    for (i, c) in serial[SERIAL_PREFIX.len()..].iter_mut().enumerate() {
+        *c = b"0123456789abcdef"[(id >> (60 - 4 * i)) as usize & 0xF];
+    }
+    core::str::from_utf8(serial).unwrap()
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     info!("Hello RMK BLE!");
@@ -142,11 +186,18 @@ async fn main(spawner: Spawner) {
     let mut host_resources = HostResources::new();
     let stack = build_ble_stack(sdc, ble_addr(), &mut rng_gen, &mut host_resources).await;
 
-    // Initialize usb driver
-    let driver = Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs));
+    // Initialize usb driver, which answers joystick tuning requests itself
+    static USB_ENDPOINTS: StaticCell<raw_hid::Endpoints<'static, UsbDriver>> = StaticCell::new();
+    let usb_endpoints: &'static _ = USB_ENDPOINTS.init(raw_hid::Endpoints::new());
+    let driver = raw_hid::TuningDriver::new(
+        Driver::new(p.USBD, Irqs, HardwareVbusDetect::new(Irqs)),
+        usb_endpoints,
+    );
 
-    // Initialize flash
-    let flash = Flash::take(mpsl, p.NVMC);
+    // Initialize flash, which rmk's storage shares with the settings
+    static FLASH: StaticCell<Mutex<CriticalSectionRawMutex, Flash<'static>>> = StaticCell::new();
+    let flash: &'static _ = FLASH.init(Mutex::new(Flash::take(mpsl, p.NVMC)));
+    let settings_store = SettingsStore::load(SharedFlash::new(flash)).await;
 
     // Initialize IO Pins
     let (input_pins, output_pins) = config_matrix_pins_nrf!(
@@ -179,14 +230,14 @@ async fn main(spawner: Spawner) {
         pid: 0x4643,
         manufacturer: "Luca",
         product_name: "Corne",
-        serial_number: "vial:f64c2b3c:000001",
+        serial_number: serial_number(),
     };
     let vial_config = VialConfig::new(VIAL_KEYBOARD_ID, VIAL_KEYBOARD_DEF);
     let ble_battery_config = BleBatteryConfig::new(None, true, None, false);
     let storage_config = StorageConfig {
-        start_addr: 0xA0000,
-        num_sectors: 6,
-        clear_storage: true,
+        start_addr: memory::CENTRAL_STORAGE_START,
+        num_sectors: memory::CENTRAL_STORAGE_SECTORS,
+        clear_storage: memory::CLEAR_STORAGE,
         ..Default::default()
     };
     let rmk_config = RmkConfig {
@@ -200,18 +251,21 @@ async fn main(spawner: Spawner) {
     // Initialize keyboard stuff
     // Initialize the storage and keymap
     let mut default_keymap = keymap::get_default_keymap();
+    symbols::apply_to_default_keymap(&mut default_keymap, settings::get().host_layout);
     let mut behavior_config = BehaviorConfig {
-        tri_layer: Some([1, 2, 3]),
         keyboard_macros: KeyboardMacrosConfig {
             macro_sequences: keymap::get_macro_sequences(),
         },
+        combo: keymap::get_combos(),
+        fork: keymap::get_forks(),
+        mouse_key: keymap::get_mouse_key_config(),
         ..BehaviorConfig::default()
     };
     let mut encoder_map = keymap::get_default_encoder_map();
     let (keymap, mut storage) = initialize_encoder_keymap_and_storage(
         &mut default_keymap,
         &mut encoder_map,
-        flash,
+        SharedFlash::new(flash),
         &storage_config,
         behavior_config,
     )
@@ -231,22 +285,34 @@ async fn main(spawner: Spawner) {
         read_peripheral_addresses::<1, _, ROW, COL, NUM_LAYER, NUM_ENCODER>(&mut storage).await;
 
     // Initialize the encoder process or
-    let mut adc_device = NrfAdc::new(
-        saadc,
-        [AnalogEventType::Battery, AnalogEventType::Joystick(2)],
-        Duration::from_ticks(20),
-        Some(Duration::from_ticks(300)),
+    // Poll fast while the stick is in use, slow down after 2s at rest
+    let mut adc_device = AdaptiveAdc::new(
+        NrfAdc::new(
+            saadc,
+            [AnalogEventType::Battery, AnalogEventType::Joystick(2)],
+            Duration::from_ticks(20),
+            Some(Duration::from_ticks(300)),
+        ),
+        Duration::from_millis(30),
+        Duration::from_secs(2),
     );
     let mut batt_proc = BatteryProcessor::new(2000, 2806, &keymap);
     let mut joy_proc = joystick::JoystickProcessor::new(
         [[10, 0], [0, 10]],
         [50, 50],
+        40, /* activity threshold */
         6,
         &keymap,
         joystick::KeyboardSide::Left,
+        protocol::JoystickParams {
+            deadzone: 4,
+            role: protocol::Role::Scroll,
+            ..Default::default()
+        },
     );
 
     // Initialize the controllers
+    let mut custom_key_controller = CustomKeyController::new(&keymap);
     let mut light_controller: LightController<Output> =
         LightController::new(rmk::config::LightConfig {
             capslock: None,
@@ -263,7 +329,7 @@ async fn main(spawner: Spawner) {
             EVENT_CHANNEL => [joy_proc, batt_proc],
         },
         keyboard.run(),
-        join(
+        join5(
             run_peripheral_manager::<4, 7, 0, COL_OFFSET, _>(0, peripheral_addrs[0], &stack),
             run_rmk(
                 &keymap,
@@ -273,6 +339,9 @@ async fn main(spawner: Spawner) {
                 &mut light_controller,
                 rmk_config,
             ),
+            raw_hid::run_tuning_service(usb_endpoints),
+            settings_store.run(),
+            custom_key_controller.event_loop(),
         ),
     )
     .await;
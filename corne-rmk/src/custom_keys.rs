@@ -0,0 +1,516 @@
+//! Keys that this firmware handles itself instead of rmk.
+//!
+//! They are put on the `User` keycodes that rmk leaves alone. Those with a name,
+//! such as `BATT` or `LEADER`, have a keycode of their own. Those that
+//! `keyboard.toml` sets up, such as `UC(..)` or `TD(..)`, are told apart by
+//! their position and layer. They share a single keycode, except for those
+//! that hold back other keys, which toggle the hold-back layer. rmk publishes
+//! every key event to its controllers, which is where they are picked up.
+
+use core::cell::RefCell;
+use core::future::pending;
+use core::pin::pin;
+
+use corne_core::host_layout::HostLayout;
+use corne_core::repeat::Keystroke;
+use corne_core::unicode;
+use corne_core::usage;
+use defmt::{info, unwrap};
+use embassy_time::Timer;
+use rmk::action::{Action, KeyAction};
+use rmk::channel::{ControllerSub, CONTROLLER_CHANNEL, KEYBOARD_REPORT_CHANNEL, KEY_EVENT_CHANNEL};
+use rmk::controller::{Controller, EventController};
+use rmk::event::{ControllerEvent, KeyboardEvent, KeyboardEventPos};
+use rmk::futures::future::{select, Either};
+use rmk::heapless::Vec;
+use rmk::hid::Report;
+use rmk::keycode::KeyCode;
+use rmk::keymap::KeyMap;
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::activity;
+use crate::caps_word::{CapsWord, CapsWordKey, WordKey};
+use crate::joystick::KeyboardSide;
+use crate::keymap::{COL, NUM_ENCODER, NUM_LAYER, ROW};
+use crate::layers::Layers;
+use crate::leader_keys::{LeaderAction, LeaderKeys};
+use crate::mouse;
+use crate::overrides::{OverrideReport, Overrides};
+use crate::repeat_keys::{Last, RepeatKeys};
+use crate::settings;
+use crate::symbols;
+use crate::tap_hold_keys::{OneShotKey, TapDanceKey, TapHoldKey, TapHoldKeys, HOLD_BACK_LAYER};
+use crate::tuning;
+
+#[derive(Clone, Copy)]
+pub(crate) enum CustomKey {
+    /// Type a character, with the host's Unicode input method if its layout doesn't have it
+    Unicode(char),
+    /// Type a string
+    Text(&'static str),
+    /// Select the host's Unicode input method
+    UnicodeMethod(unicode::Method),
+    /// Switch to the next Unicode input method
+    NextUnicodeMethod,
+    /// Select the host's keyboard layout
+    HostLayout(HostLayout),
+    /// Switch to the next host keyboard layout
+    NextHostLayout,
+    /// Type the battery level in percent
+    Battery,
+    /// Switch the role of the left joystick, the right one belongs to the peripheral
+    NextJoystickRole,
+    /// Tap and hold with a tap-hold profile of its own
+    TapHold(TapHoldKey),
+    /// Different actions for a tap, a double tap, a hold and a tap followed by a hold
+    TapDance(TapDanceKey),
+    /// A modifier or layer for the next key only
+    OneShot(OneShotKey),
+    /// Capital letters until the word ends
+    CapsWord(CapsWordKey),
+    /// Start a Leader key sequence
+    Leader,
+    /// Type the last key or character again
+    Repeat,
+    /// Type the counterpart of the last key, from `[repeat] alternates`
+    AltRepeat,
+}
+
+pub(crate) enum Message {
+    /// The controller is running, before anything else
+    Start,
+    Controller(ControllerEvent),
+    /// The pending key, Caps Word or the Leader key has waited for long enough
+    Timeout,
+    /// A joystick moved the pointer or scrolled
+    Pointer,
+}
+
+/// A custom key that is found by its position, at `pos` on `layer`
+pub(crate) struct DispatchedKey {
+    layer: u8,
+    pos: (u8, u8),
+    key: CustomKey,
+}
+
+/// The last keycode that is a usage of rmk's keyboard reports, media and
+/// mouse keys follow
+const LAST_KEYBOARD_USAGE: u16 = 0xA4;
+
+// `CUSTOM_KEYS`, the `User` keycode of each custom key with a name,
+// `DISPATCHED_KEYCODE` and `DISPATCHED_KEYS`, the other custom keys
+include!(concat!(env!("OUT_DIR"), "/custom_keys_generated.rs"));
+
+/// The custom key of an event with `action`, with the `layers` that are on
+fn custom_key(event: &KeyboardEvent, action: KeyAction, layers: u32) -> Option<CustomKey> {
+    let keycode = match action {
+        KeyAction::Single(Action::Key(keycode)) => keycode,
+        // Keys that hold back others, found by position as well
+        KeyAction::Single(Action::LayerToggle(HOLD_BACK_LAYER)) => DISPATCHED_KEYCODE,
+        _ => return None,
+    };
+    if keycode != DISPATCHED_KEYCODE {
+        return CUSTOM_KEYS
+            .iter()
+            .find(|(k, _)| *k == keycode)
+            .map(|(_, custom)| *custom);
+    }
+    let KeyboardEventPos::Key(pos) = event.pos else {
+        return None;
+    };
+    // rmk took the action from the highest of them, those above are transparent
+    DISPATCHED_KEYS
+        .iter()
+        .filter(|k| k.pos == (pos.row, pos.col) && layers & 1 << k.layer != 0)
+        .max_by_key(|k| k.layer)
+        .map(|k| k.key)
+}
+
+pub(crate) struct CustomKeyController<'a> {
+    sub: ControllerSub,
+    keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+    /// Last battery level that rmk reported
+    battery: Option<u8>,
+    tap_hold: TapHoldKeys,
+    caps_word: CapsWord,
+    leader: LeaderKeys,
+    repeat: RepeatKeys,
+    layers: Layers,
+    overrides: Overrides,
+    started: bool,
+    /// How many keys of each modifier are down, for capital `Unicode` letters
+    /// and the Repeat key
+    modifiers: [u8; 8],
+    /// Usages of the other keys that are down, which the reports of custom
+    /// keys keep down
+    keys: Vec<u8, 6>,
+    /// A keystroke of Repeat or Alt-Repeat is down
+    repeat_down: bool,
+}
+
+/// Press and release the hidden key at `pos`
+async fn tap_hidden(pos: (u8, u8)) {
+    for pressed in [true, false] {
+        KEY_EVENT_CHANNEL
+            .send(KeyboardEvent::key(pos.0, pos.1, pressed))
+            .await;
+    }
+}
+
+impl<'a> CustomKeyController<'a> {
+    pub(crate) fn new(keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>) -> Self {
+        Self {
+            sub: unwrap!(CONTROLLER_CHANNEL.subscriber()),
+            keymap,
+            battery: None,
+            tap_hold: TapHoldKeys::default(),
+            caps_word: CapsWord::default(),
+            leader: LeaderKeys::default(),
+            repeat: RepeatKeys::default(),
+            layers: Layers::default(),
+            overrides: Overrides::default(),
+            started: false,
+            modifiers: [0; 8],
+            keys: Vec::new(),
+            repeat_down: false,
+        }
+    }
+
+    /// HID modifier bits of the modifier keys that are down
+    fn held_modifiers(&self) -> u8 {
+        self.modifiers
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .fold(0, |bits, (i, _)| bits | 1 << i)
+    }
+
+    /// Send `modifiers` and `key` to the host, past rmk, along with the other
+    /// keys that are down so that they stay down
+    async fn send_report(&self, modifiers: u8, key: u8) {
+        let mut keycodes = [0; 6];
+        let keys = core::iter::once(key)
+            .chain(self.keys.iter().copied().filter(|&k| k != key))
+            .filter(|&k| k != usage::NONE);
+        for (slot, key) in keycodes.iter_mut().zip(keys) {
+            *slot = key;
+        }
+        KEYBOARD_REPORT_CHANNEL
+            .send(Report::KeyboardReport(KeyboardReport {
+                modifier: modifiers,
+                reserved: 0,
+                leds: 0,
+                keycodes,
+            }))
+            .await;
+    }
+
+    /// Type `c`, bypassing the keymap. What is down is down again afterwards.
+    async fn type_char(&self, c: char) {
+        let settings = settings::get();
+        if let Some(keystroke) = settings.host_layout.keystroke(c) {
+            self.send_report(keystroke.modifiers, keystroke.key).await;
+            self.send_report(0, usage::NONE).await;
+            if keystroke.dead {
+                self.send_report(0, usage::SPACE).await;
+                self.send_report(0, usage::NONE).await;
+            }
+        } else {
+            for report in unicode::sequence(settings.unicode_method, c).as_slice() {
+                self.send_report(report.modifiers, report.key).await;
+            }
+        }
+        // The sequences have modifiers of their own and release the others
+        if self.held_modifiers() != 0 {
+            self.send_report(self.held_modifiers(), usage::NONE).await;
+        }
+    }
+
+    /// Type the letter `c`, a capital one while Shift is down or Caps Word is on
+    async fn type_letter(&self, c: char) {
+        let shift = self.held_modifiers() & (usage::MOD_LSHIFT | usage::MOD_RSHIFT) != 0;
+        let capital = shift || self.caps_word.is_active();
+        // Caps Lock takes care of those that the host's layout has
+        let caps_lock = !shift && settings::get().host_layout.keystroke(c).is_some();
+        if !capital || caps_lock {
+            self.type_char(c).await;
+            return;
+        }
+        for c in c.to_uppercase() {
+            self.type_char(c).await;
+        }
+    }
+
+    async fn type_text(&self, text: &str) {
+        for c in text.chars() {
+            self.type_char(c).await;
+        }
+    }
+
+    async fn run_leader_action(&mut self, action: LeaderAction) {
+        match action {
+            LeaderAction::Text(text) => {
+                self.type_text(text).await;
+                self.repeat.record(Last::Text(text));
+            }
+            LeaderAction::Unicode(c) => {
+                self.type_letter(c).await;
+                self.repeat.record(Last::Char(c));
+            }
+            // Recorded when rmk publishes its event
+            LeaderAction::Key(pos) => tap_hidden(pos).await,
+        }
+    }
+
+    /// Hold down `keystroke` with the modifiers that are down, until Repeat
+    /// or Alt-Repeat is released
+    async fn press_repeated(&mut self, keystroke: Keystroke) {
+        self.send_report(keystroke.modifiers | self.held_modifiers(), keystroke.key)
+            .await;
+        self.repeat_down = true;
+    }
+
+    async fn repeat(&mut self) {
+        match self.repeat.last() {
+            Some(Last::Keystroke(keystroke)) => self.press_repeated(keystroke).await,
+            Some(Last::Char(c)) => self.type_letter(c).await,
+            Some(Last::Text(text)) => self.type_text(text).await,
+            Some(Last::Key(pos)) => tap_hidden(pos).await,
+            None => {}
+        }
+    }
+
+    async fn alt_repeat(&mut self) {
+        if let Some(keystroke) = self.repeat.alternate(settings::get().host_layout) {
+            self.press_repeated(keystroke).await;
+        }
+    }
+
+    async fn type_battery(&self) {
+        let Some(level) = self.battery else {
+            self.type_char('?').await;
+            return;
+        };
+        let digits = [level / 100, level / 10 % 10, level % 10];
+        let skip = if level >= 100 {
+            0
+        } else if level >= 10 {
+            1
+        } else {
+            2
+        };
+        for d in &digits[skip..] {
+            self.type_char(char::from(b'0' + d)).await;
+        }
+        self.type_char('%').await;
+    }
+
+    fn next_joystick_role(&self) {
+        let stick = KeyboardSide::Left.stick();
+        let mut params = tuning::params(stick);
+        params.role = params.role.next();
+        info!("Left joystick: {}", defmt::Debug2Format(&params.role));
+        tuning::set_params(stick, params);
+    }
+
+    fn set_unicode_method(&self, method: unicode::Method) {
+        info!("Unicode input method: {}", defmt::Debug2Format(&method));
+        settings::update(|s| s.unicode_method = method);
+    }
+
+    fn set_host_layout(&self, layout: HostLayout) {
+        info!("Host layout: {}", defmt::Debug2Format(&layout));
+        settings::update(|s| s.host_layout = layout);
+        symbols::apply(self.keymap, layout);
+    }
+}
+
+impl Controller for CustomKeyController<'_> {
+    type Event = Message;
+
+    async fn process_event(&mut self, event: Self::Event) {
+        let (key_event, action) = match event {
+            Message::Controller(ControllerEvent::Key(key_event, action)) => (key_event, action),
+            Message::Controller(ControllerEvent::Battery(level)) => {
+                self.battery = Some(level);
+                return;
+            }
+            Message::Controller(ControllerEvent::Layer(highest)) => {
+                self.layers.layer_changed(highest).await;
+                return;
+            }
+            Message::Start => {
+                self.layers.restore_default_layer().await;
+                return;
+            }
+            Message::Timeout => {
+                self.tap_hold.timeout().await;
+                self.caps_word.timeout().await;
+                if let Some(action) = self.leader.timeout(&mut self.tap_hold).await {
+                    self.run_leader_action(action).await;
+                }
+                return;
+            }
+            Message::Pointer => {
+                self.tap_hold.pointer().await;
+                self.caps_word.stop().await;
+                return;
+            }
+            _ => return,
+        };
+        let custom = custom_key(&key_event, action, self.layers.active());
+        // Every key of the hold-back layer, which is on while a `TapHold`,
+        // `TapDance` or `OneShot` key is undecided or a Leader sequence is typed
+        let held_back = matches!(action, KeyAction::Single(Action::Key(KeyCode::No)));
+        if self
+            .tap_hold
+            .key_event(key_event, held_back, self.leader.is_active())
+            .await
+        {
+            return;
+        }
+        self.layers.key_event(&key_event, action).await;
+        mouse::key_event(action, key_event.pressed);
+        if held_back && self.leader.is_active() {
+            if let (true, KeyboardEventPos::Key(pos)) = (key_event.pressed, key_event.pos) {
+                if let Some(action) = self
+                    .leader
+                    .key_pressed((pos.row, pos.col), &mut self.tap_hold)
+                    .await
+                {
+                    self.run_leader_action(action).await;
+                }
+            }
+            return;
+        }
+        // The output of a fork is the keycode of dispatched keys as well
+        if custom.is_none() {
+            let report = self.overrides.key_event(
+                &key_event,
+                action,
+                self.layers.active(),
+                self.held_modifiers(),
+            );
+            match report {
+                Some(OverrideReport::Press(keystroke)) => {
+                    self.send_report(keystroke.modifiers, keystroke.key).await;
+                    return;
+                }
+                Some(OverrideReport::Tap(keystroke)) => {
+                    self.send_report(keystroke.modifiers, keystroke.key).await;
+                    self.send_report(self.held_modifiers(), usage::NONE).await;
+                    return;
+                }
+                Some(OverrideReport::Release) => {
+                    self.send_report(self.held_modifiers(), usage::NONE).await;
+                    return;
+                }
+                None => {}
+            }
+        }
+        if let KeyAction::Single(Action::Key(keycode)) = action {
+            let modifier = (keycode as u16).wrapping_sub(KeyCode::LCtrl as u16) as usize;
+            if let Some(count) = self.modifiers.get_mut(modifier) {
+                *count = if key_event.pressed {
+                    count.saturating_add(1)
+                } else {
+                    count.saturating_sub(1)
+                };
+            } else if (KeyCode::A as u16..=LAST_KEYBOARD_USAGE).contains(&(keycode as u16)) {
+                let key = keycode as u8;
+                if !key_event.pressed {
+                    self.keys.retain(|&k| k != key);
+                } else if !self.keys.contains(&key) {
+                    // rmk has no room for more either
+                    let _ = self.keys.push(key);
+                }
+            }
+        }
+        if !key_event.pressed {
+            if matches!(custom, Some(CustomKey::Repeat | CustomKey::AltRepeat)) && self.repeat_down
+            {
+                self.repeat_down = false;
+                self.send_report(self.held_modifiers(), usage::NONE).await;
+            }
+            return;
+        }
+        let word = match custom {
+            None => WordKey::of(action),
+            Some(CustomKey::Unicode(_)) => WordKey::Word,
+            Some(CustomKey::Text(_)) => WordKey::Other,
+            Some(_) => WordKey::Ignored,
+        };
+        self.caps_word.key_pressed(&key_event, word).await;
+        match custom {
+            Some(CustomKey::Unicode(c)) => {
+                self.type_letter(c).await;
+                self.repeat.record(Last::Char(c));
+            }
+            Some(CustomKey::Text(text)) => {
+                self.type_text(text).await;
+                self.repeat.record(Last::Text(text));
+            }
+            Some(CustomKey::UnicodeMethod(method)) => self.set_unicode_method(method),
+            Some(CustomKey::NextUnicodeMethod) => {
+                self.set_unicode_method(settings::get().unicode_method.next())
+            }
+            Some(CustomKey::HostLayout(layout)) => self.set_host_layout(layout),
+            Some(CustomKey::NextHostLayout) => {
+                self.set_host_layout(settings::get().host_layout.next())
+            }
+            Some(CustomKey::Battery) => self.type_battery().await,
+            Some(CustomKey::NextJoystickRole) => self.next_joystick_role(),
+            Some(CustomKey::TapHold(key)) => self.tap_hold.press_tap_hold(key_event, key).await,
+            Some(CustomKey::TapDance(key)) => self.tap_hold.press_tap_dance(key_event, key),
+            Some(CustomKey::OneShot(key)) => {
+                activity::forget_pointer_use();
+                self.tap_hold.press_one_shot(key_event, key)
+            }
+            Some(CustomKey::CapsWord(key)) => {
+                activity::forget_pointer_use();
+                self.caps_word.toggle(key).await
+            }
+            Some(CustomKey::Leader) => self.leader.start(&mut self.tap_hold).await,
+            Some(CustomKey::Repeat) => self.repeat().await,
+            Some(CustomKey::AltRepeat) => self.alt_repeat().await,
+            None => self.repeat.key_pressed(action, self.held_modifiers()),
+        }
+    }
+
+    async fn next_message(&mut self) -> Self::Event {
+        if !self.started {
+            self.started = true;
+            return Message::Start;
+        }
+        let deadline = [
+            self.tap_hold.deadline(),
+            self.caps_word.deadline(),
+            self.leader.deadline(),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let timeout = async {
+            match deadline {
+                Some(deadline) => Timer::at(deadline).await,
+                None => pending().await,
+            }
+        };
+        let wants_pointer = self.tap_hold.wants_pointer() || self.caps_word.is_active();
+        let pointer = async {
+            if wants_pointer {
+                activity::wait_for_pointer_use().await
+            } else {
+                pending().await
+            }
+        };
+        let others = select(pin!(timeout), pin!(pointer));
+        match select(pin!(self.sub.next_message_pure()), others).await {
+            Either::Left((event, _)) => Message::Controller(event),
+            Either::Right((Either::Left(_), _)) => Message::Timeout,
+            Either::Right((Either::Right(_), _)) => Message::Pointer,
+        }
+    }
+}
+
+impl EventController for CustomKeyController<'_> {}
@@ -1,5 +1,6 @@
 use core::cell::RefCell;
 
+use corne_core::protocol::{Curve, JoystickParams, Role};
 use rmk::channel::KEYBOARD_REPORT_CHANNEL;
 use rmk::event::Event;
 use rmk::hid::Report;
@@ -7,11 +8,106 @@ use rmk::input_device::{InputProcessor, ProcessResult};
 use rmk::keymap::KeyMap;
 use usbd_hid::descriptor::MouseReport;
 
+use crate::activity;
+use crate::keymap::{ACTIVITY_KEY, COL_OFFSET};
+use crate::mouse;
+use crate::tuning;
+
 pub enum KeyboardSide {
     Left,
     Right,
 }
 
+impl KeyboardSide {
+    /// Index of the stick on this side, as used by the tuning protocol
+    pub fn stick(&self) -> usize {
+        match self {
+            KeyboardSide::Left => 0,
+            KeyboardSide::Right => 1,
+        }
+    }
+
+    /// The hidden key whose events tell rmk that the stick on this side is
+    /// used, as this side sends it
+    fn activity_key(&self) -> (u8, u8) {
+        let (row, col) = ACTIVITY_KEY;
+        match self {
+            KeyboardSide::Left => (row, col),
+            // The central adds the column offset to the peripheral's keys
+            KeyboardSide::Right => (row, col - COL_OFFSET as u8),
+        }
+    }
+}
+
+/// Deflection that the response curves map onto itself
+const CURVE_RANGE: i32 = 128;
+
+/// sin(0°), sin(5°), ..., sin(90°) in Q14
+const SIN_TABLE: [i32; 19] = [
+    0, 1428, 2845, 4240, 5604, 6924, 8192, 9397, 10531, 11585, 12551, 13421, 14189, 14849, 15396,
+    15826, 16135, 16322, 16384,
+];
+
+/// sin(deg) in Q14, interpolated between the table entries
+fn sin_q14(deg: u16) -> i32 {
+    let deg = deg % 360;
+    let (quadrant_deg, sign) = match deg {
+        0..=90 => (deg, 1),
+        91..=180 => (180 - deg, 1),
+        181..=270 => (deg - 180, -1),
+        _ => (360 - deg, -1),
+    };
+    let i = (quadrant_deg / 5) as usize;
+    let frac = (quadrant_deg % 5) as i32;
+    let v = if frac == 0 {
+        SIN_TABLE[i]
+    } else {
+        SIN_TABLE[i] + (SIN_TABLE[i + 1] - SIN_TABLE[i]) * frac / 5
+    };
+    sign * v
+}
+
+fn cos_q14(deg: u16) -> i32 {
+    sin_q14((deg % 360 + 90) % 360)
+}
+
+/// Apply the tunable parameters to a stick deflection
+fn apply_params(params: &JoystickParams, x: i16, y: i16) -> (i16, i16) {
+    let (mut x, mut y) = (x as i32, y as i32);
+
+    // Deadzone, the remaining deflection starts from zero
+    let dz = params.deadzone as i32;
+    if x * x + y * y <= dz * dz {
+        return (0, 0);
+    }
+    x = x.signum() * (x.abs() - dz).max(0);
+    y = y.signum() * (y.abs() - dz).max(0);
+
+    if params.rotation != 0 {
+        let (sin, cos) = (sin_q14(params.rotation), cos_q14(params.rotation));
+        (x, y) = ((x * cos - y * sin) >> 14, (x * sin + y * cos) >> 14);
+    }
+
+    if params.swap_axes {
+        (x, y) = (y, x);
+    }
+    if params.invert_x {
+        x = -x;
+    }
+    if params.invert_y {
+        y = -y;
+    }
+
+    let curve = |v: i32| match params.curve {
+        Curve::Linear => v,
+        Curve::Quadratic => v * v.abs() / CURVE_RANGE,
+        Curve::Cubic => v * v * v / (CURVE_RANGE * CURVE_RANGE),
+    };
+    let gain = |v: i32| v * params.gain as i32 / 100;
+    let clamp = |v: i32| v.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+    (clamp(gain(curve(x))), clamp(gain(curve(y))))
+}
+
 pub struct JoystickProcessor<
     'a,
     const ROW: usize,
@@ -20,12 +116,20 @@ pub struct JoystickProcessor<
     const NUM_ENCODER: usize,
     const N: usize,
 > {
+    /// Each output axis is the sum of the biased inputs divided by these weights,
+    /// a weight of 0 ignores the input. Use `invert_x`, `invert_y` and
+    /// `swap_axes` in `JoystickParams` to flip the axes rather than negative weights.
     transform: [[i16; N]; N],
     bias: [i16; N],
+    /// Biased input that counts as moving the stick, ADC noise at rest stays
+    /// below it even without a deadzone
+    activity_threshold: u16,
     keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
     record: [i16; N],
     resolution: u16,
     side: KeyboardSide,
+    /// Whether the stick was moved at the last report
+    active: bool,
 }
 
 impl<
@@ -37,20 +141,27 @@ impl<
         const N: usize,
     > JoystickProcessor<'a, ROW, COL, NUM_LAYER, NUM_ENCODER, N>
 {
+    /// `params` are the initial tunable parameters of the stick on `side`,
+    /// they can be changed at runtime over raw HID.
     pub fn new(
         transform: [[i16; N]; N],
         bias: [i16; N],
+        activity_threshold: u16,
         resolution: u16,
         keymap: &'a RefCell<KeyMap<'a, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
         side: KeyboardSide,
+        params: JoystickParams,
     ) -> Self {
+        tuning::add_stick(side.stick(), params);
         Self {
             transform,
             bias,
+            activity_threshold,
             resolution,
             keymap,
             record: [0; N],
             side,
+            active: false,
         }
     }
     async fn generate_report(&mut self) {
@@ -76,19 +187,47 @@ impl<
         }
 
         // debug!("JoystickProcessor::generate_report: report = {:?}", report);
+        let params = tuning::params(self.side.stick());
+        let moved = self
+            .record
+            .iter()
+            .any(|v| v.unsigned_abs() > self.activity_threshold);
+        let (x, y) = if moved {
+            apply_params(&params, report[0], report[1])
+        } else {
+            (0, 0)
+        };
+
+        // Only report while the stick is deflected, plus once when it returns to
+        // rest, so that a resting stick doesn't keep the keyboard awake
+        let deflected = x != 0 || y != 0;
+        if deflected {
+            activity::report_pointer_use(self.side.activity_key());
+        } else if !self.active {
+            return;
+        }
+        self.active = deflected;
+
+        let x = x.clamp(i8::MIN as i16, i8::MAX as i16) as i8;
+        let y = y.clamp(i8::MIN as i16, i8::MAX as i16) as i8;
+
         // map to mouse
-        let mouse_report = MouseReport {
-            buttons: 0,
-            x: 0,
-            y: 0,
-            // x: (report[0].clamp(i8::MIN as i16, i8::MAX as i16)) as i8,
-            // y: (report[1].clamp(i8::MIN as i16, i8::MAX as i16)) as i8,
-            // wheel: -128i8 / 16,
-            // pan: 0,
-            // wheel: (report[0].clamp(i8::MIN as i16, i8::MAX as i16)) as i8,
-            // pan: (report[1].clamp(i8::MIN as i16, i8::MAX as i16)) as i8,
-            wheel: 0,
-            pan: 0,
+        let mouse_report = match params.role {
+            Role::Off => return,
+            Role::Pointer => MouseReport {
+                buttons: mouse::buttons(),
+                x,
+                y,
+                wheel: 0,
+                pan: 0,
+            },
+            Role::Scroll => MouseReport {
+                buttons: mouse::buttons(),
+                x: 0,
+                y: 0,
+                wheel: y,
+                pan: x,
+            },
         };
         self.send_report(Report::MouseReport(mouse_report)).await;
     }
@@ -111,6 +250,7 @@ impl<
                 for (rec, e) in self.record.iter_mut().zip(event.iter()) {
                     *rec = e.value;
                 }
+                tuning::record_sample(self.side.stick(), self.record[0], self.record[1]);
                 // debug!("Joystick info: {:#?}", self.record);
                 self.generate_report().await;
                 ProcessResult::Stop
@@ -0,0 +1,133 @@
+//! Conditional layers and the default layer.
+//!
+//! rmk has a single tri-layer, so the `[[conditional_layer]]` rules are
+//! followed here instead, see `layer_rules.rs`. The layers that keys turn on
+//! are tracked from their events: `mo!` keys right away, and `lt!` keys once
+//! rmk reports a layer change while they are down, since it decides whether
+//! they are held. A layer that a rule turns on is held on with a hidden `mo!`
+//! key.
+//!
+//! rmk forgets the default layer that `df!` keys select, it is saved with the
+//! settings instead and selected again at boot with a hidden `df!` key.
+
+use corne_core::layer_rules::{self, Rule};
+use defmt::info;
+use rmk::action::{Action, KeyAction};
+use rmk::channel::KEY_EVENT_CHANNEL;
+use rmk::event::{KeyboardEvent, KeyboardEventPos};
+use rmk::heapless::Vec;
+
+use crate::settings;
+
+// `LAYER_RULES`, `RULE_KEYS`, the hidden key that holds on each layer that
+// rules turn on, and `DEFAULT_LAYER_KEYS`, the hidden key that selects each
+// layer that can be the default one
+include!(concat!(env!("OUT_DIR"), "/layers_generated.rs"));
+
+/// A key that is down and turns on a layer
+struct LayerKey {
+    pos: (u8, u8),
+    layer: u8,
+    /// Whether the layer is on, `lt!` keys may still be tapped
+    on: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct Layers {
+    keys: Vec<LayerKey, 8>,
+    /// Layers that rules hold on
+    held: u32,
+}
+
+async fn send(pos: (u8, u8), pressed: bool) {
+    KEY_EVENT_CHANNEL
+        .send(KeyboardEvent::key(pos.0, pos.1, pressed))
+        .await;
+}
+
+impl Layers {
+    /// Select the saved default layer, once rmk is running. rmk starts on the
+    /// base layer anyway.
+    pub(crate) async fn restore_default_layer(&self) {
+        let layer = settings::get().default_layer;
+        if let Some(&(_, pos)) = DEFAULT_LAYER_KEYS.iter().find(|(l, _)| *l == layer) {
+            info!("Default layer: {}", layer);
+            send(pos, true).await;
+            send(pos, false).await;
+        }
+    }
+
+    /// A key with `action` was pressed or released
+    pub(crate) async fn key_event(&mut self, event: &KeyboardEvent, action: KeyAction) {
+        let KeyboardEventPos::Key(pos) = event.pos else {
+            return;
+        };
+        let pos = (pos.row, pos.col);
+        // Those of the rules themselves
+        if RULE_KEYS.iter().any(|(_, p)| *p == pos) {
+            return;
+        }
+        if !event.pressed {
+            let before = self.keys.len();
+            self.keys.retain(|k| k.pos != pos);
+            if self.keys.len() != before {
+                self.update().await;
+            }
+            return;
+        }
+        let (layer, on) = match action {
+            KeyAction::Single(Action::LayerOn(layer)) => (layer, true),
+            KeyAction::TapHold(_, Action::LayerOn(layer)) => (layer, false),
+            KeyAction::Single(Action::DefaultLayer(layer)) => {
+                let known = layer == 0 || DEFAULT_LAYER_KEYS.iter().any(|(l, _)| *l == layer);
+                if known && settings::get().default_layer != layer {
+                    settings::update(|s| s.default_layer = layer);
+                }
+                return;
+            }
+            _ => return,
+        };
+        // Without room the key is left out, as if it wasn't held
+        if self.keys.push(LayerKey { pos, layer, on }).is_ok() && on {
+            self.update().await;
+        }
+    }
+
+    /// The layers that are on as far as key events tell, as bits: the default
+    /// layer, those of keys that are down and those that rules hold on
+    pub(crate) fn active(&self) -> u32 {
+        self.keys.iter().filter(|k| k.on).fold(
+            1 << settings::get().default_layer | self.held,
+            |active, k| active | 1 << k.layer,
+        )
+    }
+
+    /// rmk reported a layer change, with the highest layer that is on
+    pub(crate) async fn layer_changed(&mut self, highest: u8) {
+        let mut changed = false;
+        for key in self.keys.iter_mut().filter(|k| !k.on && k.layer <= highest) {
+            key.on = true;
+            changed = true;
+        }
+        if changed {
+            self.update().await;
+        }
+    }
+
+    /// Hold on the layers that the rules ask for, and only those
+    async fn update(&mut self) {
+        let active = self
+            .keys
+            .iter()
+            .filter(|k| k.on)
+            .fold(0, |active, k| active | 1 << k.layer);
+        let wanted = layer_rules::resolve(&LAYER_RULES, active);
+        for &(layer, pos) in RULE_KEYS.iter() {
+            let bit = 1 << layer;
+            if wanted & bit != self.held & bit {
+                send(pos, wanted & bit != 0).await;
+            }
+        }
+        self.held = wanted;
+    }
+}
@@ -0,0 +1,85 @@
+//! The Leader key, after which a sequence of keys types what it stands for.
+//!
+//! The keys of a sequence are told apart by their position on the base layer,
+//! see `leader.rs` for the trie. While they are typed the hold-back layer is
+//! on, as for tap-hold keys, so that rmk doesn't type them.
+
+use corne_core::leader::{Leader, Node, Step, NONE};
+use embassy_time::{Duration, Instant};
+
+use crate::tap_hold_keys::TapHoldKeys;
+
+/// What a Leader key sequence does
+#[derive(Clone, Copy)]
+pub(crate) enum LeaderAction {
+    Text(&'static str),
+    /// Typed as `CustomKey::Unicode`
+    Unicode(char),
+    /// Tap the hidden key at `(row, col)`
+    Key((u8, u8)),
+}
+
+// `LEADER_TIMEOUT_MS`, `LEADER_NODES` and `LEADER_ACTIONS`
+include!(concat!(env!("OUT_DIR"), "/leader_generated.rs"));
+
+#[derive(Default)]
+pub(crate) struct LeaderKeys {
+    /// The sequence so far, with the time of its last key
+    active: Option<(Leader<'static>, Instant)>,
+}
+
+impl LeaderKeys {
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// When the sequence ends unless another key comes
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        let (_, last) = self.active.as_ref()?;
+        Some(*last + Duration::from_millis(LEADER_TIMEOUT_MS))
+    }
+
+    /// The Leader key was pressed, `tap_hold` toggles the hold-back layer
+    pub(crate) async fn start(&mut self, tap_hold: &mut TapHoldKeys) {
+        if self.is_active() {
+            return;
+        }
+        tap_hold.tap_gate().await;
+        self.active = Some((Leader::new(&LEADER_NODES), Instant::now()));
+    }
+
+    /// The key at `(row, col)` was pressed, with the action of the sequence
+    /// if that ends it
+    pub(crate) async fn key_pressed(
+        &mut self,
+        pos: (u8, u8),
+        tap_hold: &mut TapHoldKeys,
+    ) -> Option<LeaderAction> {
+        let (leader, last) = self.active.as_mut()?;
+        *last = Instant::now();
+        let action = match leader.key((u16::from(pos.0) << 8) | u16::from(pos.1)) {
+            Step::Pending => return None,
+            Step::Done(action) => Some(LEADER_ACTIONS[action as usize]),
+            Step::Failed => None,
+        };
+        self.stop(tap_hold).await;
+        action
+    }
+
+    /// End the sequence if it has waited for long enough, with its action if any
+    pub(crate) async fn timeout(&mut self, tap_hold: &mut TapHoldKeys) -> Option<LeaderAction> {
+        if self.deadline()? > Instant::now() {
+            return None;
+        }
+        let (leader, _) = self.active.as_ref()?;
+        let action = leader.timeout().map(|a| LEADER_ACTIONS[a as usize]);
+        self.stop(tap_hold).await;
+        action
+    }
+
+    async fn stop(&mut self, tap_hold: &mut TapHoldKeys) {
+        if self.active.take().is_some() {
+            tap_hold.tap_gate().await;
+        }
+    }
+}
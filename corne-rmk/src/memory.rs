@@ -0,0 +1,11 @@
+//! Where each half keeps its data in flash, from `[memory]` in keyboard.toml.
+//!
+//! The build script checks them against `memory.x` and the bootloader, and
+//! the linker that the image ends before them.
+
+// Each binary only uses those of its own half
+#![allow(dead_code)]
+
+// `CENTRAL_STORAGE_START`, `CENTRAL_STORAGE_SECTORS`, `PERIPHERAL_STORAGE_START`,
+// `PERIPHERAL_STORAGE_SECTORS`, `CLEAR_STORAGE` and `SETTINGS_ADDR`
+include!(concat!(env!("OUT_DIR"), "/memory_generated.rs"));
@@ -0,0 +1,36 @@
+//! Mouse buttons shared by rmk's mouse keys and the joysticks.
+//!
+//! rmk's mouse keys and `JoystickProcessor` both send mouse reports, and each
+//! report carries the state of every button. The buttons that mouse keys hold
+//! down are recorded here so that joystick reports keep them pressed instead
+//! of releasing them. Only the central sees key events, the peripheral's
+//! buttons stay up.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use rmk::action::{Action, KeyAction};
+use rmk::keycode::KeyCode;
+
+static BUTTONS: AtomicU8 = AtomicU8::new(0);
+
+/// The mouse buttons that mouse keys hold down, as in a mouse report
+pub(crate) fn buttons() -> u8 {
+    BUTTONS.load(Ordering::Relaxed)
+}
+
+/// A key with `action` was pressed or released
+#[allow(dead_code)] // Only the central's custom keys see key events
+pub(crate) fn key_event(action: KeyAction, pressed: bool) {
+    let KeyAction::Single(Action::Key(keycode)) = action else {
+        return;
+    };
+    let button = (keycode as u16).wrapping_sub(KeyCode::MouseBtn1 as u16);
+    if button >= 5 {
+        return;
+    }
+    if pressed {
+        BUTTONS.fetch_or(1 << button, Ordering::Relaxed);
+    } else {
+        BUTTONS.fetch_and(!(1 << button), Ordering::Relaxed);
+    }
+}
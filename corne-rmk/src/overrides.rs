@@ -0,0 +1,81 @@
+//! Key overrides that `layers` limits to some layers.
+//!
+//! rmk's forks match the action of a key, not its layer, so the fork of such an
+//! override is on every layer. It keeps the modifiers down and its output is
+//! the keycode of dispatched keys, which does nothing in rmk. rmk publishes the
+//! action its fork resolved to, so that keycode at the position of a trigger
+//! means that the modifiers matched, and the controller sends the report of
+//! `LayerOverride::keystroke()` itself: the modifiers in it are exactly those
+//! that the host gets. The layer is found here the way rmk resolves the key,
+//! the highest one that is on and has it.
+
+use corne_core::overrides::LayerOverride;
+use corne_core::repeat::Keystroke;
+use defmt::warn;
+use rmk::action::{Action, KeyAction};
+use rmk::event::{KeyboardEvent, KeyboardEventPos};
+use rmk::heapless::Vec;
+use rmk::keycode::KeyCode;
+
+use crate::custom_keys::DISPATCHED_KEYCODE;
+
+/// The trigger of an override limited to some layers, at `pos` on `layer`
+pub(crate) struct OverrideKey {
+    layer: u8,
+    pos: (u8, u8),
+    rule: LayerOverride,
+}
+
+// `OVERRIDE_KEYS`, the trigger of each override at each of its positions
+include!(concat!(env!("OUT_DIR"), "/overrides_generated.rs"));
+
+/// What to send to the host for an override
+pub(crate) enum OverrideReport {
+    /// Hold down the keystroke until the trigger is released
+    Press(Keystroke),
+    /// Type the keystroke, too many triggers are down to hold it
+    Tap(Keystroke),
+    /// Release what the trigger held down
+    Release,
+}
+
+#[derive(Default)]
+pub(crate) struct Overrides {
+    /// Triggers that hold down their keystroke
+    down: Vec<(u8, u8), 4>,
+}
+
+impl Overrides {
+    /// A key with `action` was pressed or released, while `layers` are on and
+    /// `modifiers` are down. What to send if an override took care of it.
+    pub(crate) fn key_event(
+        &mut self,
+        event: &KeyboardEvent,
+        action: KeyAction,
+        layers: u32,
+        modifiers: u8,
+    ) -> Option<OverrideReport> {
+        let KeyboardEventPos::Key(pos) = event.pos else {
+            return None;
+        };
+        let pos = (pos.row, pos.col);
+        if !event.pressed {
+            let i = self.down.iter().position(|p| *p == pos)?;
+            self.down.swap_remove(i);
+            return Some(OverrideReport::Release);
+        }
+        if !matches!(action, KeyAction::Single(Action::Key(k)) if k == DISPATCHED_KEYCODE) {
+            return None;
+        }
+        let key = OVERRIDE_KEYS
+            .iter()
+            .filter(|k| k.pos == pos && layers & 1 << k.layer != 0)
+            .max_by_key(|k| k.layer)?;
+        let keystroke = key.rule.keystroke(key.layer, modifiers);
+        if self.down.push(pos).is_err() {
+            warn!("Too many overrides at once");
+            return Some(OverrideReport::Tap(keystroke));
+        }
+        Some(OverrideReport::Press(keystroke))
+    }
+}
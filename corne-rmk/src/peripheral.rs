@@ -1,12 +1,19 @@
 #![no_std]
 #![no_main]
 
+mod activity;
+mod adaptive_adc;
 mod joystick;
 mod keymap;
+mod memory;
+mod mouse;
+mod tuning;
 
 #[macro_use]
 mod macros;
 
+use crate::adaptive_adc::AdaptiveAdc;
+use corne_core::protocol;
 use defmt::{info, unwrap};
 use embassy_executor::Spawner;
 use embassy_nrf::gpio::{Input, Output};
@@ -139,18 +146,24 @@ async fn main(spawner: Spawner) {
     );
     interrupt::SAADC.set_priority(interrupt::Priority::P3);
     saadc.calibrate().await;
-    let mut adc_dev = NrfAdc::new(
-        saadc,
-        [AnalogEventType::Battery, AnalogEventType::Joystick(2)],
-        Duration::from_ticks(20),        /* polling interval */
-        Some(Duration::from_ticks(300)), /* light sleep interval */
+    let mut adc_dev = AdaptiveAdc::new(
+        NrfAdc::new(
+            saadc,
+            [AnalogEventType::Battery, AnalogEventType::Joystick(2)],
+            Duration::from_ticks(20),        /* polling interval */
+            Some(Duration::from_ticks(300)), /* light sleep interval */
+        ),
+        Duration::from_millis(30), /* polling interval at rest */
+        Duration::from_secs(2),    /* time until at rest */
     );
     let mut default_keymap = keymap::get_default_keymap();
     let mut behavior_config = BehaviorConfig {
-        tri_layer: Some([1, 2, 3]),
         keyboard_macros: KeyboardMacrosConfig {
             macro_sequences: keymap::get_macro_sequences(),
         },
+        combo: keymap::get_combos(),
+        fork: keymap::get_forks(),
+        mouse_key: keymap::get_mouse_key_config(),
         ..BehaviorConfig::default()
     };
     let mut encoder_map = keymap::get_default_encoder_map();
@@ -159,9 +172,11 @@ async fn main(spawner: Spawner) {
     let mut joy_proc = joystick::JoystickProcessor::new(
         [[1000, 0], [0, 1000]],
         [-8200, 0],
+        400, /* activity threshold, there is no deadzone */
         6,
         &keymap,
         joystick::KeyboardSide::Right,
+        protocol::JoystickParams::default(),
     );
 
     let (input_pins, output_pins) = config_matrix_pins_nrf!(
@@ -176,9 +191,9 @@ async fn main(spawner: Spawner) {
     // Initialize flash
     // nRF52840's bootloader starts from 0xF4000(976K)
     let storage_config = StorageConfig {
-        start_addr: 0x60000, // 384K
-        num_sectors: 32,     // 128K
-        clear_storage: true,
+        start_addr: memory::PERIPHERAL_STORAGE_START,
+        num_sectors: memory::PERIPHERAL_STORAGE_SECTORS,
+        clear_storage: memory::CLEAR_STORAGE,
         ..Default::default()
     };
     let flash = Flash::take(mpsl, p.NVMC);
@@ -0,0 +1,232 @@
+//! Joystick tuning requests on the VIA raw HID interface, answered before rmk
+//! sees them.
+//!
+//! rmk's Vial leaves VIA's custom value commands unhandled, so the USB driver
+//! is wrapped instead. Every 32 byte report that an OUT endpoint receives is
+//! offered to `tuning::handle_report` first, and those on the joystick channel
+//! never reach rmk. The answers and streamed samples are written by
+//! `run_tuning_service` to the IN endpoint of the same interface. The driver
+//! doesn't know interfaces, but embassy-usb's HID class allocates the IN
+//! endpoint of an interface right before its OUT endpoint, and the driver
+//! panics at init if an interrupt OUT endpoint comes after anything else. rmk
+//! keeps writing to that endpoint as well, so every interrupt IN endpoint is
+//! kept behind a mutex.
+//!
+//! Only the central has the USB driver, and it only tunes its own stick, see
+//! `tuning.rs`. Over Bluetooth, Vial goes through rmk's own HID service and the
+//! tuning requests stay unanswered.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_usb::driver::{
+    self, Driver, Endpoint, EndpointAddress, EndpointAllocError, EndpointError, EndpointIn as _,
+    EndpointInfo, EndpointType,
+};
+
+use corne_core::protocol::REPORT_SIZE;
+
+use crate::tuning;
+
+/// Interrupt IN endpoints that can be shared, more than rmk has HID interfaces
+const MAX_SHARED: usize = 4;
+
+/// No endpoint has answered a tuning request yet
+const UNKNOWN: u8 = u8::MAX;
+
+type SharedEndpoint<'d, D> = Mutex<CriticalSectionRawMutex, Option<<D as Driver<'d>>::EndpointIn>>;
+
+/// The interrupt IN endpoints of the wrapped driver
+pub(crate) struct Endpoints<'d, D: Driver<'d>> {
+    shared: [SharedEndpoint<'d, D>; MAX_SHARED],
+    /// The one of the VIA interface, found from the first tuning request
+    via: AtomicU8,
+}
+
+impl<'d, D: Driver<'d>> Endpoints<'d, D> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            shared: [const { Mutex::new(None) }; MAX_SHARED],
+            via: AtomicU8::new(UNKNOWN),
+        }
+    }
+
+    /// Write `report` to the IN endpoint of the VIA interface
+    async fn write_via(&self, report: &[u8; REPORT_SIZE]) {
+        let Some(shared) = self.shared.get(self.via.load(Ordering::Relaxed) as usize) else {
+            return;
+        };
+        if let Some(endpoint) = shared.lock().await.as_mut() {
+            // Nobody to answer while the host is gone
+            let _ = endpoint.write(report).await;
+        }
+    }
+}
+
+/// rmk's USB driver, with tuning requests taken out of the VIA interface
+pub(crate) struct TuningDriver<'d, D: Driver<'d>> {
+    inner: D,
+    endpoints: &'d Endpoints<'d, D>,
+    /// Shared endpoints handed out so far
+    used: usize,
+    /// Index of the last IN endpoint, if it is shared
+    last_in: Option<u8>,
+    /// Whether the last endpoint was an interrupt IN endpoint
+    after_interrupt_in: bool,
+}
+
+impl<'d, D: Driver<'d>> TuningDriver<'d, D> {
+    pub(crate) fn new(inner: D, endpoints: &'d Endpoints<'d, D>) -> Self {
+        Self {
+            inner,
+            endpoints,
+            used: 0,
+            last_in: None,
+            after_interrupt_in: false,
+        }
+    }
+}
+
+impl<'d, D: Driver<'d>> Driver<'d> for TuningDriver<'d, D> {
+    type EndpointOut = EndpointOut<'d, D>;
+    type EndpointIn = EndpointIn<'d, D>;
+    type ControlPipe = D::ControlPipe;
+    type Bus = D::Bus;
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_type: EndpointType,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        // Otherwise answers would go to the IN endpoint of another interface
+        defmt::assert!(
+            ep_type != EndpointType::Interrupt || self.after_interrupt_in,
+            "An interrupt OUT endpoint has no IN endpoint right before it"
+        );
+        self.after_interrupt_in = false;
+        let inner =
+            self.inner
+                .alloc_endpoint_out(ep_type, ep_addr, max_packet_size, interval_ms)?;
+        Ok(EndpointOut {
+            inner,
+            paired_in: self.last_in.take(),
+            endpoints: self.endpoints,
+        })
+    }
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_type: EndpointType,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        let inner = self
+            .inner
+            .alloc_endpoint_in(ep_type, ep_addr, max_packet_size, interval_ms)?;
+        self.last_in = None;
+        self.after_interrupt_in = ep_type == EndpointType::Interrupt;
+        let info = *inner.info();
+        let shared = match self.endpoints.shared.get(self.used) {
+            Some(shared) if ep_type == EndpointType::Interrupt => shared,
+            _ => return Ok(EndpointIn::Plain(inner)),
+        };
+        // Nothing else holds it before the driver has started
+        let Ok(mut slot) = shared.try_lock() else {
+            return Ok(EndpointIn::Plain(inner));
+        };
+        *slot = Some(inner);
+        self.last_in = Some(self.used as u8);
+        self.used += 1;
+        Ok(EndpointIn::Shared { shared, info })
+    }
+
+    fn start(self, control_max_packet_size: u16) -> (Self::Bus, Self::ControlPipe) {
+        self.inner.start(control_max_packet_size)
+    }
+}
+
+pub(crate) struct EndpointOut<'d, D: Driver<'d>> {
+    inner: D::EndpointOut,
+    /// The shared IN endpoint allocated right before this one, of the same interface
+    paired_in: Option<u8>,
+    endpoints: &'d Endpoints<'d, D>,
+}
+
+impl<'d, D: Driver<'d>> Endpoint for EndpointOut<'d, D> {
+    fn info(&self) -> &EndpointInfo {
+        self.inner.info()
+    }
+
+    async fn wait_enabled(&mut self) {
+        self.inner.wait_enabled().await
+    }
+}
+
+impl<'d, D: Driver<'d>> driver::EndpointOut for EndpointOut<'d, D> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, EndpointError> {
+        loop {
+            let len = self.inner.read(buf).await?;
+            let report = <&[u8; REPORT_SIZE]>::try_from(&buf[..len]);
+            let (Some(paired_in), Ok(report)) = (self.paired_in, report) else {
+                return Ok(len);
+            };
+            let Some(answer) = tuning::handle_report(report) else {
+                return Ok(len);
+            };
+            self.endpoints.via.store(paired_in, Ordering::Relaxed);
+            tuning::send(answer).await;
+        }
+    }
+}
+
+pub(crate) enum EndpointIn<'d, D: Driver<'d>> {
+    Plain(D::EndpointIn),
+    Shared {
+        shared: &'d SharedEndpoint<'d, D>,
+        info: EndpointInfo,
+    },
+}
+
+impl<'d, D: Driver<'d>> Endpoint for EndpointIn<'d, D> {
+    fn info(&self) -> &EndpointInfo {
+        match self {
+            EndpointIn::Plain(inner) => inner.info(),
+            EndpointIn::Shared { info, .. } => info,
+        }
+    }
+
+    async fn wait_enabled(&mut self) {
+        match self {
+            EndpointIn::Plain(inner) => inner.wait_enabled().await,
+            EndpointIn::Shared { shared, .. } => {
+                if let Some(inner) = shared.lock().await.as_mut() {
+                    inner.wait_enabled().await
+                }
+            }
+        }
+    }
+}
+
+impl<'d, D: Driver<'d>> driver::EndpointIn for EndpointIn<'d, D> {
+    async fn write(&mut self, buf: &[u8]) -> Result<(), EndpointError> {
+        match self {
+            EndpointIn::Plain(inner) => inner.write(buf).await,
+            EndpointIn::Shared { shared, .. } => match shared.lock().await.as_mut() {
+                Some(inner) => inner.write(buf).await,
+                None => Err(EndpointError::Disabled),
+            },
+        }
+    }
+}
+
+/// Send the answers to tuning requests and streamed samples to the host
+pub(crate) async fn run_tuning_service<'d, D: Driver<'d>>(endpoints: &Endpoints<'d, D>) {
+    loop {
+        let report = tuning::receive().await;
+        endpoints.write_via(&report).await;
+    }
+}
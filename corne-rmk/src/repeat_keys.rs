@@ -0,0 +1,94 @@
+//! The Repeat and Alt-Repeat keys.
+//!
+//! The last thing typed is taken from the key events that rmk publishes, after
+//! layers and hidden keys are resolved, so it doesn't matter how it was typed.
+//! See `repeat.rs` for the keys that Alt-Repeat sends instead.
+
+use rmk::action::{Action, KeyAction};
+use rmk::keycode::{KeyCode, ModifierCombination};
+
+use corne_core::host_layout::HostLayout;
+use corne_core::repeat::{self, Keystroke};
+
+// `ALTERNATES`, the pairs of `[repeat] alternates` for each host layout, and
+// `MACRO_KEYS`, the hidden key of each macro
+include!(concat!(env!("OUT_DIR"), "/repeat_generated.rs"));
+
+/// What was typed last
+#[derive(Clone, Copy)]
+pub(crate) enum Last {
+    Keystroke(Keystroke),
+    /// Typed as `CustomKey::Unicode`
+    Char(char),
+    Text(&'static str),
+    /// Tap the hidden key at `(row, col)`
+    Key((u8, u8)),
+}
+
+#[derive(Default)]
+pub(crate) struct RepeatKeys {
+    last: Option<Last>,
+}
+
+/// HID modifier bits of `modifiers`
+fn modifier_bits(modifiers: ModifierCombination) -> u8 {
+    let bits = u8::from(modifiers.ctrl())
+        | u8::from(modifiers.shift()) << 1
+        | u8::from(modifiers.alt()) << 2
+        | u8::from(modifiers.gui()) << 3;
+    if modifiers.right() {
+        bits << 4
+    } else {
+        bits
+    }
+}
+
+/// The keystroke of `keycode` with `modifiers`, if it is a key that types
+fn keystroke(keycode: KeyCode, modifiers: u8) -> Option<Keystroke> {
+    let code = keycode as u16;
+    // rmk's keycodes match the HID usages up to ExSel, the others are its own
+    let types = (KeyCode::A as u16..=0xA4).contains(&code) && keycode != KeyCode::CapsLock;
+    types.then_some(Keystroke {
+        key: code as u8,
+        modifiers,
+    })
+}
+
+impl RepeatKeys {
+    pub(crate) fn last(&self) -> Option<Last> {
+        self.last
+    }
+
+    pub(crate) fn record(&mut self, last: Last) {
+        self.last = Some(last);
+    }
+
+    /// A key with `action` was pressed while `modifiers` were down
+    pub(crate) fn key_pressed(&mut self, action: KeyAction, modifiers: u8) {
+        let last = match action {
+            KeyAction::Single(Action::Key(keycode)) => {
+                let code = keycode as u16;
+                let macro_index = code.wrapping_sub(KeyCode::Macro0 as u16) as usize;
+                match MACRO_KEYS.get(macro_index) {
+                    Some(&pos) if code >= KeyCode::Macro0 as u16 => Some(Last::Key(pos)),
+                    _ => keystroke(keycode, modifiers).map(Last::Keystroke),
+                }
+            }
+            KeyAction::Single(Action::KeyWithModifier(keycode, with)) => {
+                keystroke(keycode, modifiers | modifier_bits(with)).map(Last::Keystroke)
+            }
+            _ => None,
+        };
+        if let Some(last) = last {
+            self.last = Some(last);
+        }
+    }
+
+    /// What Alt-Repeat sends on the host's `layout`
+    pub(crate) fn alternate(&self, layout: HostLayout) -> Option<Keystroke> {
+        let Some(Last::Keystroke(last)) = self.last else {
+            return None;
+        };
+        repeat::alternate(&ALTERNATES[layout as usize], last)
+    }
+}
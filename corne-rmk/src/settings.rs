@@ -0,0 +1,210 @@
+//! Settings of this firmware that rmk's storage doesn't know about.
+//!
+//! They live in a flash sector of their own, as a log of fixed size records:
+//! every change is written to the next erased slot, and the sector is only
+//! erased once it is full. The last valid record wins.
+
+use core::cell::Cell;
+
+use corne_core::host_layout::HostLayout;
+use corne_core::unicode;
+use defmt::{error, info};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::Timer;
+use embedded_storage_async::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
+use nrf_mpsl::{Flash, FlashError};
+
+use crate::keymap::NUM_LAYER;
+use crate::memory::SETTINGS_ADDR;
+
+/// Flash size of the nRF52840
+const FLASH_SIZE: usize = 1024 * 1024;
+
+const SECTOR_SIZE: u32 = 0x1000;
+const RECORD_SIZE: usize = 16;
+const NUM_SLOTS: u32 = SECTOR_SIZE / RECORD_SIZE as u32;
+
+/// First byte of a record, an erased slot reads 0xFF
+const RECORD_MAGIC: u8 = 0x5E;
+
+/// How long to wait for more changes before writing them
+const SAVE_DELAY_SECS: u64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Settings {
+    pub unicode_method: unicode::Method,
+    pub host_layout: HostLayout,
+    /// The layer that `df!` keys selected last, see `layers.rs`
+    pub default_layer: u8,
+}
+
+impl Settings {
+    const DEFAULT: Settings = Settings {
+        unicode_method: unicode::Method::Linux,
+        host_layout: HostLayout::Us,
+        default_layer: 0,
+    };
+
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut record = [0; RECORD_SIZE];
+        record[0] = RECORD_MAGIC;
+        record[1] = self.unicode_method as u8;
+        record[2] = self.host_layout as u8;
+        record[3] = self.default_layer;
+        record[RECORD_SIZE - 1] = checksum(&record[..RECORD_SIZE - 1]);
+        record
+    }
+
+    fn decode(record: &[u8; RECORD_SIZE]) -> Option<Self> {
+        if record[0] != RECORD_MAGIC
+            || record[RECORD_SIZE - 1] != checksum(&record[..RECORD_SIZE - 1])
+        {
+            return None;
+        }
+        Some(Self {
+            unicode_method: unicode::Method::from_u8(record[1])
+                .unwrap_or(Self::DEFAULT.unicode_method),
+            host_layout: HostLayout::from_u8(record[2]).unwrap_or(Self::DEFAULT.host_layout),
+            // Saved by a firmware with other layers
+            default_layer: Some(record[3])
+                .filter(|&layer| usize::from(layer) < NUM_LAYER)
+                .unwrap_or(Self::DEFAULT.default_layer),
+        })
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    !data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+static SETTINGS: Mutex<CriticalSectionRawMutex, Cell<Settings>> =
+    Mutex::new(Cell::new(Settings::DEFAULT));
+
+static SAVE_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Current settings
+pub(crate) fn get() -> Settings {
+    SETTINGS.lock(|s| s.get())
+}
+
+/// Change the settings, they are written to flash a moment later
+pub(crate) fn update(f: impl FnOnce(&mut Settings)) {
+    SETTINGS.lock(|s| {
+        let mut settings = s.get();
+        f(&mut settings);
+        s.set(settings);
+    });
+    SAVE_SIGNAL.signal(());
+}
+
+/// The settings sector, and where the next record goes
+pub(crate) struct SettingsStore<F: NorFlash> {
+    flash: F,
+    slot: u32,
+}
+
+impl<F: NorFlash> SettingsStore<F> {
+    /// Read the settings from flash, before anything uses them
+    pub(crate) async fn load(mut flash: F) -> Self {
+        let slot = match Self::read_last(&mut flash).await {
+            Ok((settings, slot)) => {
+                info!("Loaded settings: {}", defmt::Debug2Format(&settings));
+                SETTINGS.lock(|s| s.set(settings));
+                slot
+            }
+            Err(_) => {
+                error!("Cannot read settings, using defaults");
+                NUM_SLOTS
+            }
+        };
+        Self { flash, slot }
+    }
+
+    /// Settings of the last valid record, and the first erased slot
+    async fn read_last(flash: &mut F) -> Result<(Settings, u32), F::Error> {
+        let mut settings = Settings::DEFAULT;
+        for slot in 0..NUM_SLOTS {
+            let mut record = [0; RECORD_SIZE];
+            flash
+                .read(SETTINGS_ADDR + slot * RECORD_SIZE as u32, &mut record)
+                .await?;
+            if record.iter().all(|b| *b == 0xFF) {
+                return Ok((settings, slot));
+            }
+            if let Some(s) = Settings::decode(&record) {
+                settings = s;
+            }
+        }
+        Ok((settings, NUM_SLOTS))
+    }
+
+    async fn save(&mut self, settings: &Settings) -> Result<(), F::Error> {
+        if self.slot >= NUM_SLOTS {
+            self.flash
+                .erase(SETTINGS_ADDR, SETTINGS_ADDR + SECTOR_SIZE)
+                .await?;
+            self.slot = 0;
+        }
+        self.flash
+            .write(
+                SETTINGS_ADDR + self.slot * RECORD_SIZE as u32,
+                &settings.encode(),
+            )
+            .await?;
+        self.slot += 1;
+        Ok(())
+    }
+
+    /// Write every change of the settings back to flash
+    pub(crate) async fn run(mut self) {
+        loop {
+            SAVE_SIGNAL.wait().await;
+            Timer::after_secs(SAVE_DELAY_SECS).await;
+            SAVE_SIGNAL.reset();
+            if self.save(&get()).await.is_err() {
+                error!("Cannot write settings");
+            }
+        }
+    }
+}
+
+/// The flash, shared between rmk's storage and the settings
+pub(crate) struct SharedFlash(&'static AsyncMutex<CriticalSectionRawMutex, Flash<'static>>);
+
+impl SharedFlash {
+    pub(crate) fn new(flash: &'static AsyncMutex<CriticalSectionRawMutex, Flash<'static>>) -> Self {
+        Self(flash)
+    }
+}
+
+impl ErrorType for SharedFlash {
+    type Error = FlashError;
+}
+
+impl ReadNorFlash for SharedFlash {
+    const READ_SIZE: usize = <Flash as ReadNorFlash>::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.lock().await.read(offset, bytes).await
+    }
+
+    fn capacity(&self) -> usize {
+        FLASH_SIZE
+    }
+}
+
+impl NorFlash for SharedFlash {
+    const WRITE_SIZE: usize = <Flash as NorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <Flash as NorFlash>::ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        self.0.lock().await.erase(from, to).await
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.lock().await.write(offset, bytes).await
+    }
+}
@@ -0,0 +1,46 @@
+//! `SYM(..)` keys, which type their symbol with whatever layout the host is set to.
+//!
+//! The build script works out the action of every such key for each host layout.
+//! The default keymap has the US ones, and they are swapped when the host layout
+//! is selected, which also replaces changes made to these keys with Vial.
+//! On layouts where the symbol is a dead key, it waits for the next key as usual.
+
+use core::cell::RefCell;
+
+use corne_core::host_layout::HostLayout;
+use rmk::action::KeyAction;
+use rmk::keymap::KeyMap;
+
+use crate::keymap::{COL, NUM_ENCODER, NUM_LAYER, ROW};
+
+pub(crate) struct SymbolKey {
+    layer: usize,
+    row: usize,
+    col: usize,
+    /// Action for each `HostLayout`
+    actions: [KeyAction; HostLayout::ALL.len()],
+}
+
+// `SYMBOL_KEYS`, every `SYM(..)` key in keyboard.toml
+include!(concat!(env!("OUT_DIR"), "/symbols_generated.rs"));
+
+/// Set up the symbol keys for `layout` in the default keymap, before it is handed to rmk
+pub(crate) fn apply_to_default_keymap(
+    keymap: &mut [[[KeyAction; COL]; ROW]; NUM_LAYER],
+    layout: HostLayout,
+) {
+    for key in SYMBOL_KEYS.iter() {
+        keymap[key.layer][key.row][key.col] = key.actions[layout as usize];
+    }
+}
+
+/// Switch the symbol keys of the running keymap to `layout`
+pub(crate) fn apply(
+    keymap: &RefCell<KeyMap<'_, ROW, COL, NUM_LAYER, NUM_ENCODER>>,
+    layout: HostLayout,
+) {
+    let mut keymap = keymap.borrow_mut();
+    for key in SYMBOL_KEYS.iter() {
+        keymap.set_action_at(key.row, key.col, key.layer, key.actions[layout as usize]);
+    }
+}
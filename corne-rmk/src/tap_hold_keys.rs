@@ -0,0 +1,126 @@
+//! Tap-hold keys with a profile of their own, tap-dance keys and one-shot keys,
+//! whose action is only known after a while. See `tap_hold.rs`, `tap_dance.rs`
+//! and `one_shot.rs` for the decision.
+//!
+//! Such a key toggles the hold-back layer on, where the build script leaves
+//! every key of the matrix without an action, and the keys that come in
+//! meanwhile are replayed once it is decided. `hold_back.rs` has the protocol
+//! and tells which events to send, this only sends them to rmk, which does the
+//! actual typing and layer switching as for any other key.
+//!
+//! In Vial, the keys show up as a toggle of the hold-back layer, the hold-back
+//! layer as the last layer and the hidden keys in extra rows after the matrix.
+//! They are generated from `keyboard.toml`, and changed in Vial they stop working.
+
+use corne_core::hold_back::{Events, HoldBack, KeyEvent};
+pub(crate) use corne_core::hold_back::{OneShotKey, TapDanceKey, TapHoldKey};
+use embassy_time::Instant;
+use rmk::channel::KEY_EVENT_CHANNEL;
+use rmk::event::{KeyboardEvent, KeyboardEventPos};
+
+// `HOLD_BACK_GATE`, the hidden key that toggles the hold-back layer, and
+// `HOLD_BACK_LAYER`
+include!(concat!(env!("OUT_DIR"), "/tap_hold_generated.rs"));
+
+pub(crate) struct TapHoldKeys {
+    hold_back: HoldBack,
+}
+
+impl Default for TapHoldKeys {
+    fn default() -> Self {
+        Self {
+            hold_back: HoldBack::new(HOLD_BACK_GATE),
+        }
+    }
+}
+
+fn position(event: &KeyboardEvent) -> Option<(u8, u8)> {
+    match event.pos {
+        KeyboardEventPos::Key(pos) => Some((pos.row, pos.col)),
+        _ => None,
+    }
+}
+
+fn now_ms() -> u64 {
+    Instant::now().as_millis()
+}
+
+async fn send(events: Events) {
+    for event in events.as_slice() {
+        KEY_EVENT_CHANNEL
+            .send(KeyboardEvent::key(event.pos.0, event.pos.1, event.pressed))
+            .await;
+    }
+}
+
+impl TapHoldKeys {
+    /// When the pending key is decided by waiting
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.hold_back.deadline().map(Instant::from_millis)
+    }
+
+    /// Whether a one-shot key is pending, which the joystick decides
+    pub(crate) fn wants_pointer(&self) -> bool {
+        self.hold_back.wants_pointer()
+    }
+
+    /// Toggle the hold-back layer
+    pub(crate) async fn tap_gate(&mut self) {
+        send(self.hold_back.tap_gate()).await;
+    }
+
+    /// The tap-hold `key` was pressed
+    pub(crate) async fn press_tap_hold(&mut self, event: KeyboardEvent, key: TapHoldKey) {
+        if let Some(pos) = position(&event) {
+            send(self.hold_back.press_tap_hold(pos, key, now_ms())).await;
+        }
+    }
+
+    /// The tap-dance `key` was pressed for the first time
+    pub(crate) fn press_tap_dance(&mut self, event: KeyboardEvent, key: TapDanceKey) {
+        if let Some(pos) = position(&event) {
+            self.hold_back.press_tap_dance(pos, key, now_ms());
+        }
+    }
+
+    /// The one-shot `key` was pressed
+    pub(crate) fn press_one_shot(&mut self, event: KeyboardEvent, key: OneShotKey) {
+        if let Some(pos) = position(&event) {
+            self.hold_back.press_one_shot(pos, key);
+        }
+    }
+
+    /// Look at a key event before anything else, `true` if it's taken care of.
+    ///
+    /// `held_back` is set for events of the hold-back layer, which is also on
+    /// while `leader` is.
+    pub(crate) async fn key_event(
+        &mut self,
+        event: KeyboardEvent,
+        held_back: bool,
+        leader: bool,
+    ) -> bool {
+        let Some(pos) = position(&event) else {
+            return false;
+        };
+        let event = KeyEvent {
+            pos,
+            pressed: event.pressed,
+        };
+        let Some(events) = self.hold_back.key_event(event, held_back, leader, now_ms()) else {
+            return false;
+        };
+        send(events).await;
+        true
+    }
+
+    /// Decide the pending key if it has waited for long enough
+    pub(crate) async fn timeout(&mut self) {
+        send(self.hold_back.timeout(now_ms())).await;
+    }
+
+    /// A joystick moved the pointer or scrolled
+    pub(crate) async fn pointer(&mut self) {
+        send(self.hold_back.pointer(now_ms())).await;
+    }
+}
@@ -0,0 +1,58 @@
+//! Runtime joystick tuning over raw HID, see corne-core's `tuning.rs` for the
+//! requests and `raw_hid.rs` for how they get here.
+
+use core::cell::RefCell;
+
+use corne_core::protocol::{JoystickParams, REPORT_SIZE};
+use corne_core::tuning::Tuner;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+
+/// Answers and streamed samples on their way to the host
+static TUNING_RESPONSE_CHANNEL: Channel<CriticalSectionRawMutex, [u8; REPORT_SIZE], 4> =
+    Channel::new();
+
+static STATE: Mutex<CriticalSectionRawMutex, RefCell<Tuner>> =
+    Mutex::new(RefCell::new(Tuner::new()));
+
+/// Current parameters of a stick
+pub(crate) fn params(stick: usize) -> JoystickParams {
+    STATE.lock(|s| s.borrow().params(stick))
+}
+
+/// The stick of this half, with its initial parameters. The other half's
+/// stick can't be tuned from here.
+pub(crate) fn add_stick(stick: usize, params: JoystickParams) {
+    STATE.lock(|s| s.borrow_mut().add_stick(stick, params));
+}
+
+/// Replace the parameters of a stick
+#[allow(dead_code)] // Only the central's custom keys change them
+pub(crate) fn set_params(stick: usize, params: JoystickParams) {
+    STATE.lock(|s| s.borrow_mut().set_params(stick, params));
+}
+
+/// Record a raw reading, and push it to the host if it's streaming
+pub(crate) fn record_sample(stick: usize, x: i16, y: i16) {
+    if let Some(report) = STATE.lock(|s| s.borrow_mut().record_sample(stick, x, y)) {
+        // Drop samples rather than stalling the joystick when the host is slow
+        let _ = TUNING_RESPONSE_CHANNEL.try_send(report);
+    }
+}
+
+/// The answer to a report of the VIA interface, `None` if it's one for rmk
+#[allow(dead_code)] // Only the central talks to the host
+pub(crate) fn handle_report(report: &[u8; REPORT_SIZE]) -> Option<[u8; REPORT_SIZE]> {
+    STATE.lock(|s| s.borrow_mut().handle_report(report))
+}
+
+#[allow(dead_code)]
+pub(crate) async fn send(report: [u8; REPORT_SIZE]) {
+    TUNING_RESPONSE_CHANNEL.send(report).await;
+}
+
+#[allow(dead_code)]
+pub(crate) async fn receive() -> [u8; REPORT_SIZE] {
+    TUNING_RESPONSE_CHANNEL.receive().await
+}
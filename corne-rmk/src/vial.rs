@@ -1,3 +1,2 @@
-// Vial config is automatically generated by `build.rs`, according to `vial.json`
-// Please put `vial.json` at your project's root
+// Vial config is automatically generated by the build script, according to `keyboard.toml`
 include!(concat!(env!("OUT_DIR"), "/config_generated.rs"));
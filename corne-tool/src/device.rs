@@ -0,0 +1,217 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::profile::Profile;
+use crate::protocol::{
+    Error, JoystickParams, RawSample, Request, Response, Value, ValueId, REPORT_SIZE,
+};
+
+/// How long to wait for the keyboard to answer a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Something that exchanges raw HID reports with a keyboard
+pub trait Transport {
+    fn write(&mut self, report: &[u8; REPORT_SIZE]) -> Result<()>;
+
+    /// Read one report, `None` if nothing arrived within `timeout`
+    fn read(&mut self, timeout: Duration) -> Result<Option<[u8; REPORT_SIZE]>>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn write(&mut self, report: &[u8; REPORT_SIZE]) -> Result<()> {
+        (**self).write(report)
+    }
+
+    fn read(&mut self, timeout: Duration) -> Result<Option<[u8; REPORT_SIZE]>> {
+        (**self).read(timeout)
+    }
+}
+
+/// An error that the keyboard answered a request with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rejected(pub Error);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "keyboard rejected request: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Joystick tuning client
+pub struct Keyboard<T: Transport> {
+    transport: T,
+}
+
+impl<T: Transport> Keyboard<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Send a request and wait for the value it answers with.
+    ///
+    /// Streamed samples that arrive in between are skipped.
+    fn request(&mut self, request: Request) -> Result<Value> {
+        let (stick, id) = match request {
+            Request::Get { stick, id } => (stick, id),
+            Request::Set { stick, value } => (stick, value.id()),
+        };
+        self.transport.write(&request.encode())?;
+
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Some(report) = self.transport.read(remaining)? else {
+                bail!("no response from keyboard, tuning only works over USB");
+            };
+            match Response::decode(&report) {
+                Ok(Response::Value { stick: s, value }) if s == stick && value.id() == id => {
+                    return Ok(value)
+                }
+                Ok(Response::Error(e)) => return Err(Rejected(e).into()),
+                // Unrelated report, e.g. a streamed sample
+                _ => continue,
+            }
+        }
+    }
+
+    pub fn get(&mut self, stick: u8, id: ValueId) -> Result<Value> {
+        self.request(Request::Get { stick, id })
+    }
+
+    pub fn set(&mut self, stick: u8, value: Value) -> Result<Value> {
+        self.request(Request::Set { stick, value })
+    }
+
+    /// Read all parameters of a stick
+    pub fn params(&mut self, stick: u8) -> Result<JoystickParams> {
+        let mut params = JoystickParams::default();
+        for id in PARAM_IDS {
+            params.set(self.get(stick, id)?);
+        }
+        Ok(params)
+    }
+
+    /// Read all parameters of a stick, `None` if the keyboard can't tune it,
+    /// as the central can't tune the stick of the other half
+    pub fn params_if_present(&mut self, stick: u8) -> Result<Option<JoystickParams>> {
+        match self.params(stick) {
+            Ok(params) => Ok(Some(params)),
+            Err(e) if e.downcast_ref() == Some(&Rejected(Error::InvalidStick)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write all parameters of a stick
+    pub fn set_params(&mut self, stick: u8, params: &JoystickParams) -> Result<()> {
+        for id in PARAM_IDS {
+            let value = params
+                .get(id)
+                .ok_or_else(|| anyhow!("{:?} is not a parameter", id))?;
+            self.set(stick, value)?;
+        }
+        Ok(())
+    }
+
+    /// Write all parameters of a stick, `false` if the keyboard can't tune it
+    pub fn set_params_if_present(&mut self, stick: u8, params: &JoystickParams) -> Result<bool> {
+        match self.set_params(stick, params) {
+            Ok(()) => Ok(true),
+            Err(e) if e.downcast_ref() == Some(&Rejected(Error::InvalidStick)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the parameters of the sticks in `profile`, with the sticks that
+    /// the keyboard can't tune and were skipped
+    pub fn load_profile(&mut self, profile: &Profile) -> Result<Vec<u8>> {
+        let mut skipped = Vec::new();
+        for (stick, params) in profile.to_params().iter().enumerate() {
+            let Some(params) = params else {
+                continue;
+            };
+            if !self.set_params_if_present(stick as u8, params)? {
+                skipped.push(stick as u8);
+            }
+        }
+        Ok(skipped)
+    }
+
+    pub fn raw_sample(&mut self, stick: u8) -> Result<RawSample> {
+        match self.get(stick, ValueId::RawAdc)? {
+            Value::RawAdc(sample) => Ok(sample),
+            v => bail!("unexpected value {:?}", v),
+        }
+    }
+
+    pub fn set_streaming(&mut self, stick: u8, enabled: bool) -> Result<()> {
+        self.set(stick, Value::Stream(enabled))?;
+        Ok(())
+    }
+
+    /// Stream the raw samples of `stick` until the returned guard is dropped
+    pub fn stream(&mut self, stick: u8) -> Result<Streaming<'_, T>> {
+        self.set_streaming(stick, true)?;
+        Ok(Streaming {
+            keyboard: self,
+            stick,
+        })
+    }
+
+    /// Wait for the next streamed sample of `stick`
+    pub fn next_sample(&mut self, stick: u8, timeout: Duration) -> Result<Option<RawSample>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Some(report) = self.transport.read(remaining)? else {
+                return Ok(None);
+            };
+            if let Ok(Response::Value {
+                stick: s,
+                value: Value::RawAdc(sample),
+            }) = Response::decode(&report)
+            {
+                if s == stick {
+                    return Ok(Some(sample));
+                }
+            }
+        }
+    }
+}
+
+/// Raw samples of a stick that the keyboard streams, turned off again on drop
+pub struct Streaming<'a, T: Transport> {
+    keyboard: &'a mut Keyboard<T>,
+    stick: u8,
+}
+
+impl<T: Transport> Streaming<'_, T> {
+    /// Wait for the next sample
+    pub fn next_sample(&mut self, timeout: Duration) -> Result<Option<RawSample>> {
+        self.keyboard.next_sample(self.stick, timeout)
+    }
+}
+
+impl<T: Transport> Drop for Streaming<'_, T> {
+    fn drop(&mut self) {
+        // Nothing to do about it here, the keyboard may be gone
+        let _ = self.keyboard.set_streaming(self.stick, false);
+    }
+}
+
+/// Every value that is a tunable parameter
+pub const PARAM_IDS: [ValueId; 7] = [
+    ValueId::Gain,
+    ValueId::Deadzone,
+    ValueId::Curve,
+    ValueId::Role,
+    ValueId::Invert,
+    ValueId::SwapAxes,
+    ValueId::Rotation,
+];
@@ -0,0 +1,22 @@
+//! Text gauges for showing live stick values in a terminal
+
+/// Full scale of the SAADC readings
+pub const ADC_MAX: i16 = 4095;
+
+/// Render `value` in `0..=max` as a bar of `width` cells, e.g. `[#####     ]`
+pub fn bar(value: i16, max: i16, width: usize) -> String {
+    let value = value.clamp(0, max) as usize;
+    let filled = (value * width + max as usize / 2) / max.max(1) as usize;
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(width - filled))
+}
+
+/// One line showing both axes of a raw sample
+pub fn sample_line(x: i16, y: i16, width: usize) -> String {
+    format!(
+        "x {:>5} {}  y {:>5} {}",
+        x,
+        bar(x, ADC_MAX, width),
+        y,
+        bar(y, ADC_MAX, width)
+    )
+}
@@ -0,0 +1,80 @@
+//! Raw HID transport through hidapi
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use hidapi::{HidApi, HidDevice};
+
+use crate::device::Transport;
+use crate::protocol::REPORT_SIZE;
+
+pub const VENDOR_ID: u16 = 0x4c4b;
+pub const PRODUCT_ID: u16 = 0x4643;
+
+/// Usage page and usage of the VIA raw HID interface
+const RAW_USAGE_PAGE: u16 = 0xFF60;
+const RAW_USAGE: u16 = 0x61;
+
+/// A raw HID interface of a connected keyboard
+pub struct DeviceInfo {
+    pub path: String,
+    pub product: String,
+    pub serial: String,
+}
+
+pub fn list_devices(api: &HidApi) -> Vec<DeviceInfo> {
+    api.device_list()
+        .filter(|d| {
+            d.vendor_id() == VENDOR_ID
+                && d.product_id() == PRODUCT_ID
+                && d.usage_page() == RAW_USAGE_PAGE
+                && d.usage() == RAW_USAGE
+        })
+        .map(|d| DeviceInfo {
+            path: d.path().to_string_lossy().into_owned(),
+            product: d.product_string().unwrap_or_default().to_owned(),
+            serial: d.serial_number().unwrap_or_default().to_owned(),
+        })
+        .collect()
+}
+
+pub struct HidTransport {
+    device: HidDevice,
+}
+
+impl HidTransport {
+    /// Open the keyboard at `path`, or the first one found
+    pub fn open(api: &HidApi, path: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(p) => p.to_owned(),
+            None => list_devices(api)
+                .into_iter()
+                .next()
+                .map(|d| d.path)
+                .ok_or_else(|| anyhow!("no keyboard found"))?,
+        };
+        let c_path = std::ffi::CString::new(path.clone())?;
+        let device = api
+            .open_path(&c_path)
+            .with_context(|| format!("cannot open {}", path))?;
+        Ok(Self { device })
+    }
+}
+
+impl Transport for HidTransport {
+    fn write(&mut self, report: &[u8; REPORT_SIZE]) -> Result<()> {
+        // The first byte is the report id, which the raw HID interface doesn't use
+        let mut buf = [0u8; REPORT_SIZE + 1];
+        buf[1..].copy_from_slice(report);
+        self.device.write(&buf)?;
+        Ok(())
+    }
+
+    fn read(&mut self, timeout: Duration) -> Result<Option<[u8; REPORT_SIZE]>> {
+        let mut buf = [0u8; REPORT_SIZE];
+        let n = self
+            .device
+            .read_timeout(&mut buf, timeout.as_millis() as i32)?;
+        Ok((n == REPORT_SIZE).then_some(buf))
+    }
+}
@@ -0,0 +1,13 @@
+//! Host side companion of the corne-rmk firmware.
+//!
+//! Talks the joystick tuning protocol over the keyboard's raw HID interface.
+//! The codec is the firmware's own `protocol.rs` from corne-core, so both ends can't drift apart.
+
+pub use corne_core::protocol;
+
+pub mod device;
+pub mod gauge;
+#[cfg(feature = "hid")]
+pub mod hid;
+pub mod mock;
+pub mod profile;
@@ -0,0 +1,199 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use corne_tool::device::{Keyboard, Transport};
+use corne_tool::gauge;
+use corne_tool::mock::MockDevice;
+use corne_tool::profile::Profile;
+use corne_tool::protocol::{Curve, Role, Value};
+
+#[derive(Parser)]
+#[command(about = "Tune the corne-rmk joysticks over raw HID")]
+struct Cli {
+    /// HID path of the keyboard, defaults to the first one found
+    #[arg(long, global = true)]
+    device: Option<String>,
+    /// Talk to an in-process mock keyboard instead of real hardware
+    #[arg(long, global = true)]
+    mock: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List connected keyboards
+    List,
+    /// Print the parameters of both sticks as a profile
+    Dump,
+    /// Set a parameter of a stick
+    Set {
+        stick: Stick,
+        param: Param,
+        /// New value, e.g. `150`, `quadratic`, `scroll`, `xy` or `on`
+        value: String,
+    },
+    /// Show live raw values of a stick
+    Watch {
+        stick: Stick,
+        /// Width of each gauge in characters
+        #[arg(long, default_value_t = 30)]
+        width: usize,
+    },
+    /// Save the parameters of both sticks to a TOML profile
+    Save { file: PathBuf },
+    /// Load a TOML profile into the keyboard
+    Load { file: PathBuf },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Stick {
+    Left,
+    Right,
+}
+
+impl Stick {
+    fn index(self) -> u8 {
+        match self {
+            Stick::Left => 0,
+            Stick::Right => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Param {
+    Gain,
+    Deadzone,
+    Curve,
+    Role,
+    Invert,
+    SwapAxes,
+    Rotation,
+}
+
+fn parse_value(param: Param, s: &str) -> Result<Value> {
+    let number = || s.parse::<u16>().map_err(|_| anyhow!("expected a number"));
+    Ok(match param {
+        Param::Gain => Value::Gain(number()?),
+        Param::Deadzone => Value::Deadzone(number()?),
+        Param::Rotation => Value::Rotation(number()?),
+        Param::Curve => Value::Curve(match s {
+            "linear" => Curve::Linear,
+            "quadratic" => Curve::Quadratic,
+            "cubic" => Curve::Cubic,
+            _ => bail!("expected linear, quadratic or cubic"),
+        }),
+        Param::Role => Value::Role(match s {
+            "off" => Role::Off,
+            "pointer" => Role::Pointer,
+            "scroll" => Role::Scroll,
+            _ => bail!("expected off, pointer or scroll"),
+        }),
+        Param::Invert => match s {
+            "none" => Value::Invert { x: false, y: false },
+            "x" => Value::Invert { x: true, y: false },
+            "y" => Value::Invert { x: false, y: true },
+            "xy" => Value::Invert { x: true, y: true },
+            _ => bail!("expected none, x, y or xy"),
+        },
+        Param::SwapAxes => Value::SwapAxes(match s {
+            "on" | "true" => true,
+            "off" | "false" => false,
+            _ => bail!("expected on or off"),
+        }),
+    })
+}
+
+#[cfg(feature = "hid")]
+fn open(cli: &Cli) -> Result<Box<dyn Transport>> {
+    if cli.mock {
+        return Ok(Box::new(MockDevice::new()));
+    }
+    let api = hidapi::HidApi::new()?;
+    Ok(Box::new(corne_tool::hid::HidTransport::open(
+        &api,
+        cli.device.as_deref(),
+    )?))
+}
+
+#[cfg(not(feature = "hid"))]
+fn open(cli: &Cli) -> Result<Box<dyn Transport>> {
+    if cli.mock {
+        return Ok(Box::new(MockDevice::new()));
+    }
+    bail!("built without the `hid` feature, only --mock is available")
+}
+
+#[cfg(feature = "hid")]
+fn list() -> Result<()> {
+    let api = hidapi::HidApi::new()?;
+    for d in corne_tool::hid::list_devices(&api) {
+        println!("{}  {} ({})", d.path, d.product, d.serial);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "hid"))]
+fn list() -> Result<()> {
+    bail!("built without the `hid` feature")
+}
+
+fn read_profile<T: Transport>(keyboard: &mut Keyboard<T>) -> Result<Profile> {
+    Ok(Profile::from_params(&[
+        keyboard.params_if_present(0)?,
+        keyboard.params_if_present(1)?,
+    ]))
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if let Command::List = cli.command {
+        return list();
+    }
+
+    let mut keyboard = Keyboard::new(open(&cli)?);
+    match cli.command {
+        Command::List => unreachable!(),
+        Command::Dump => print!("{}", read_profile(&mut keyboard)?.to_toml()?),
+        Command::Set {
+            stick,
+            param,
+            value,
+        } => {
+            let value = keyboard.set(stick.index(), parse_value(param, &value)?)?;
+            println!("{:?}", value);
+        }
+        Command::Watch { stick, width } => {
+            let stop = Arc::new(AtomicBool::new(false));
+            let stop_on_ctrl_c = stop.clone();
+            ctrlc::set_handler(move || stop_on_ctrl_c.store(true, Ordering::Relaxed))?;
+            let mut streaming = keyboard.stream(stick.index())?;
+            while !stop.load(Ordering::Relaxed) {
+                if let Some(sample) = streaming.next_sample(Duration::from_millis(100))? {
+                    print!("\r{}", gauge::sample_line(sample.x, sample.y, width));
+                    use std::io::Write;
+                    std::io::stdout().flush()?;
+                }
+            }
+            println!();
+        }
+        Command::Save { file } => {
+            std::fs::write(&file, read_profile(&mut keyboard)?.to_toml()?)?;
+            println!("Saved to {}", file.display());
+        }
+        Command::Load { file } => {
+            let profile = Profile::from_toml(&std::fs::read_to_string(&file)?)?;
+            for stick in keyboard.load_profile(&profile)? {
+                let name = if stick == 0 { "left" } else { "right" };
+                eprintln!("Skipped the {} stick, the keyboard can't tune it", name);
+            }
+            println!("Loaded {}", file.display());
+        }
+    }
+    Ok(())
+}
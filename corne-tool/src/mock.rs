@@ -0,0 +1,64 @@
+//! In-process keyboard that answers the tuning protocol with the firmware's `Tuner`,
+//! so everything above the transport can be exercised without hardware.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::Result;
+use corne_core::tuning::Tuner;
+
+use crate::device::Transport;
+use crate::protocol::{Error, JoystickParams, Response, REPORT_SIZE};
+
+pub struct MockDevice {
+    tuner: Tuner,
+    outgoing: VecDeque<[u8; REPORT_SIZE]>,
+}
+
+impl MockDevice {
+    /// A keyboard that can tune both sticks
+    pub fn new() -> Self {
+        Self::with_sticks(&[0, 1])
+    }
+
+    /// A keyboard that can only tune `sticks`, as the central only has its own
+    pub fn with_sticks(sticks: &[usize]) -> Self {
+        let mut tuner = Tuner::new();
+        for &stick in sticks {
+            tuner.add_stick(stick, JoystickParams::default());
+        }
+        Self {
+            tuner,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    /// Simulate a new ADC reading of a stick
+    pub fn push_sample(&mut self, stick: usize, x: i16, y: i16) {
+        if let Some(report) = self.tuner.record_sample(stick, x, y) {
+            self.outgoing.push_back(report);
+        }
+    }
+}
+
+impl Default for MockDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for MockDevice {
+    fn write(&mut self, report: &[u8; REPORT_SIZE]) -> Result<()> {
+        // The firmware leaves other reports to rmk's VIA, which doesn't know them either
+        let response = self
+            .tuner
+            .handle_report(report)
+            .unwrap_or_else(|| Response::Error(Error::UnknownCommand).encode());
+        self.outgoing.push_back(response);
+        Ok(())
+    }
+
+    fn read(&mut self, _timeout: Duration) -> Result<Option<[u8; REPORT_SIZE]>> {
+        Ok(self.outgoing.pop_front())
+    }
+}
@@ -0,0 +1,119 @@
+//! Tuning profiles, the parameters of the sticks stored as TOML
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{Curve, JoystickParams, Role};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CurveName {
+    Linear,
+    Quadratic,
+    Cubic,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleName {
+    Off,
+    Pointer,
+    Scroll,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StickProfile {
+    pub gain: u16,
+    pub deadzone: u16,
+    pub curve: CurveName,
+    pub role: RoleName,
+    #[serde(default)]
+    pub invert_x: bool,
+    #[serde(default)]
+    pub invert_y: bool,
+    #[serde(default)]
+    pub swap_axes: bool,
+    #[serde(default)]
+    pub rotation: u16,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Left out for a stick that the keyboard can't tune
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Option<StickProfile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<StickProfile>,
+}
+
+impl Profile {
+    pub fn from_params(params: &[Option<JoystickParams>; 2]) -> Self {
+        Self {
+            left: params[0].map(StickProfile::from),
+            right: params[1].map(StickProfile::from),
+        }
+    }
+
+    pub fn to_params(&self) -> [Option<JoystickParams>; 2] {
+        [
+            self.left.clone().map(Into::into),
+            self.right.clone().map(Into::into),
+        ]
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+}
+
+impl From<JoystickParams> for StickProfile {
+    fn from(p: JoystickParams) -> Self {
+        Self {
+            gain: p.gain,
+            deadzone: p.deadzone,
+            curve: match p.curve {
+                Curve::Linear => CurveName::Linear,
+                Curve::Quadratic => CurveName::Quadratic,
+                Curve::Cubic => CurveName::Cubic,
+            },
+            role: match p.role {
+                Role::Off => RoleName::Off,
+                Role::Pointer => RoleName::Pointer,
+                Role::Scroll => RoleName::Scroll,
+            },
+            invert_x: p.invert_x,
+            invert_y: p.invert_y,
+            swap_axes: p.swap_axes,
+            rotation: p.rotation,
+        }
+    }
+}
+
+impl From<StickProfile> for JoystickParams {
+    fn from(p: StickProfile) -> Self {
+        Self {
+            gain: p.gain,
+            deadzone: p.deadzone,
+            curve: match p.curve {
+                CurveName::Linear => Curve::Linear,
+                CurveName::Quadratic => Curve::Quadratic,
+                CurveName::Cubic => Curve::Cubic,
+            },
+            role: match p.role {
+                RoleName::Off => Role::Off,
+                RoleName::Pointer => Role::Pointer,
+                RoleName::Scroll => Role::Scroll,
+            },
+            invert_x: p.invert_x,
+            invert_y: p.invert_y,
+            swap_axes: p.swap_axes,
+            rotation: p.rotation % 360,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::Duration;
+
+use corne_tool::device::{Keyboard, Rejected};
+use corne_tool::mock::MockDevice;
+use corne_tool::profile::Profile;
+use corne_tool::protocol::{
+    Curve, Error, JoystickParams, Request, Role, Value, ValueId, REPORT_SIZE,
+};
+
+#[test]
+fn request_round_trip() {
+    let requests = [
+        Request::Get {
+            stick: 1,
+            id: ValueId::Deadzone,
+        },
+        Request::Set {
+            stick: 0,
+            value: Value::Rotation(270),
+        },
+        Request::Set {
+            stick: 1,
+            value: Value::Invert { x: true, y: false },
+        },
+        Request::Set {
+            stick: 0,
+            value: Value::SwapAxes(true),
+        },
+    ];
+    for r in requests {
+        assert_eq!(Request::decode(&r.encode()), Ok(r));
+    }
+}
+
+#[test]
+fn rejects_invalid_packets() {
+    let mut buf = Request::Get {
+        stick: 0,
+        id: ValueId::Gain,
+    }
+    .encode();
+    buf[3] = 5;
+    assert_eq!(Request::decode(&buf), Err(Error::InvalidStick));
+
+    let mut buf = [0u8; REPORT_SIZE];
+    buf[0] = 0x01;
+    assert_eq!(Request::decode(&buf), Err(Error::UnknownCommand));
+}
+
+#[test]
+fn set_and_read_params() {
+    let mut keyboard = Keyboard::new(MockDevice::new());
+    let params = JoystickParams {
+        gain: 150,
+        deadzone: 12,
+        curve: Curve::Quadratic,
+        role: Role::Scroll,
+        invert_x: true,
+        invert_y: false,
+        swap_axes: true,
+        rotation: 90,
+    };
+    keyboard.set_params(1, &params).unwrap();
+    assert_eq!(keyboard.params(1).unwrap(), params);
+    assert_eq!(keyboard.params(0).unwrap(), JoystickParams::default());
+}
+
+#[test]
+fn raw_adc_is_read_only() {
+    let mut keyboard = Keyboard::new(MockDevice::new());
+    let err = keyboard
+        .set(0, Value::RawAdc(Default::default()))
+        .unwrap_err();
+    assert!(err.to_string().contains("ReadOnly"));
+
+    let mut mock = keyboard.into_inner();
+    mock.push_sample(0, 2048, 1000);
+    let mut keyboard = Keyboard::new(mock);
+    let sample = keyboard.raw_sample(0).unwrap();
+    assert_eq!((sample.x, sample.y, sample.seq), (2048, 1000, 1));
+}
+
+#[test]
+fn streamed_samples_do_not_confuse_requests() {
+    let mut keyboard = Keyboard::new(MockDevice::new());
+    keyboard.set_streaming(0, true).unwrap();
+
+    let mut mock = keyboard.into_inner();
+    mock.push_sample(0, 100, 200);
+    mock.push_sample(0, 110, 210);
+    let mut keyboard = Keyboard::new(mock);
+
+    // The answer arrives behind both samples
+    assert_eq!(keyboard.get(0, ValueId::Gain).unwrap(), Value::Gain(100));
+
+    let mut mock = keyboard.into_inner();
+    mock.push_sample(0, 120, 220);
+    let mut keyboard = Keyboard::new(mock);
+    let sample = keyboard
+        .next_sample(0, Duration::from_millis(10))
+        .unwrap()
+        .unwrap();
+    assert_eq!((sample.x, sample.y, sample.seq), (120, 220, 3));
+}
+
+#[test]
+fn profile_round_trip() {
+    let params = [
+        JoystickParams {
+            role: Role::Scroll,
+            deadzone: 4,
+            ..Default::default()
+        },
+        JoystickParams {
+            curve: Curve::Cubic,
+            invert_y: true,
+            ..Default::default()
+        },
+    ];
+    let toml = Profile::from_params(&params.map(Some)).to_toml().unwrap();
+    assert!(toml.contains("role = \"scroll\""));
+    assert_eq!(
+        Profile::from_toml(&toml).unwrap().to_params(),
+        params.map(Some)
+    );
+}
+
+#[test]
+fn sticks_the_keyboard_cannot_tune_are_left_out() {
+    let mut keyboard = Keyboard::new(MockDevice::with_sticks(&[0]));
+    let err = keyboard.get(1, ValueId::Gain).unwrap_err();
+    assert_eq!(err.downcast_ref(), Some(&Rejected(Error::InvalidStick)));
+    assert_eq!(keyboard.params_if_present(1).unwrap(), None);
+    assert_eq!(
+        keyboard.params_if_present(0).unwrap(),
+        Some(JoystickParams::default())
+    );
+
+    let profile = Profile::from_params(&[Some(JoystickParams::default()), None]);
+    let toml = profile.to_toml().unwrap();
+    assert!(!toml.contains("[right]"));
+    assert_eq!(Profile::from_toml(&toml).unwrap(), profile);
+}
+
+#[test]
+fn loading_a_profile_skips_the_sticks_of_the_other_half() {
+    // The central only tunes its own, the left stick
+    let mut keyboard = Keyboard::new(MockDevice::with_sticks(&[0]));
+    let left = JoystickParams {
+        gain: 180,
+        ..Default::default()
+    };
+    let profile = Profile::from_params(&[Some(left), Some(JoystickParams::default())]);
+    assert_eq!(keyboard.load_profile(&profile).unwrap(), [1]);
+    assert_eq!(keyboard.params(0).unwrap(), left);
+}
+
+#[test]
+fn streaming_stops_when_the_guard_is_dropped() {
+    let mut keyboard = Keyboard::new(MockDevice::new());
+    {
+        let mut streaming = keyboard.stream(0).unwrap();
+        assert_eq!(streaming.next_sample(Duration::ZERO).unwrap(), None);
+    }
+    let mut mock = keyboard.into_inner();
+    mock.push_sample(0, 100, 200);
+    let mut keyboard = Keyboard::new(mock);
+    assert_eq!(
+        keyboard.next_sample(0, Duration::from_millis(10)).unwrap(),
+        None
+    );
+}